@@ -0,0 +1,856 @@
+use crate::cli::Theme;
+use crate::viewport::{self, Line, Viewport};
+use chrono::Local;
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use decom_core::{
+    recording::Clock, timeline::Entry, Error, ExportFormat, Exporter, Filter, Format, LogCollector,
+    LogRecord, LogStream, Query, QueryOptions, Search, Service, Timeline,
+};
+use std::{collections::HashMap, fs::File, io::BufWriter, path::Path};
+
+// Lines per replica loaded by each request for older history
+const FETCH_LINES: usize = 1000;
+
+type Fetch<'a> = dyn FnMut(&[Entry]) -> Vec<Option<LogRecord>> + 'a;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Mode {
+    Service,
+    Timeline,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum PromptKind {
+    /// `?` searches towards older lines.
+    Search { backward: bool },
+    /// Filter of the current pane.
+    Filter,
+    /// Filter of every pane.
+    GlobalFilter,
+    /// File to export the lines of the current pane to.
+    Export,
+}
+
+/// Search pattern, filter expression or file name being typed.
+pub struct Prompt {
+    pub kind: PromptKind,
+    pub input: String,
+    pub error: Option<String>,
+    // Where the pane was when the prompt opened, to go back to as the pattern changes
+    origin: Viewport,
+}
+
+/// Search of the records of a pane, with a search of each of its collectors.
+pub struct PaneSearch {
+    // Service searched, `None` for the timeline
+    service: Option<String>,
+    searches: Vec<Search>,
+}
+
+impl PaneSearch {
+    pub fn query(&self) -> &Query {
+        self.searches[0].query()
+    }
+
+    /// Number of matching records found so far.
+    pub fn len(&self) -> usize {
+        self.searches.iter().map(Search::len).sum()
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.searches.iter().all(Search::is_done)
+    }
+
+    // Whether the record of the entry, from the collectors searched, matches
+    fn contains(&self, entry: &Entry) -> bool {
+        self.searches
+            .get(entry.source)
+            .is_some_and(|search| search.contains(entry.seq))
+    }
+}
+
+pub struct App {
+    pub services: Vec<Service>,
+    pub collectors: Vec<LogCollector>,
+    pub current: usize,
+    // Selected tab per service, 0 is the merged view of all replicas
+    pub views: Vec<usize>,
+    // Scroll position per service, shared by its tabs
+    pub viewports: Vec<Viewport>,
+    pub timeline_viewport: Viewport,
+    // Height of the log pane as last drawn
+    pub page: usize,
+    pub stream: Option<LogStream>,
+    pub show_time: bool,
+    // Structured lines are shown as their pretty-printed object
+    pub expanded: bool,
+    // Records joined from several lines only show their first one
+    pub folded: bool,
+    // Colours written by containers are shown
+    pub ansi: bool,
+    pub mode: Mode,
+    pub timeline: Timeline,
+    // Records of each service in timestamp order, as older history is stored after newer lines
+    pub service_timelines: Vec<Timeline>,
+    pub newest_first: bool,
+    pub theme: Theme,
+    pub prompt: Option<Prompt>,
+    // Outcome of the last action, shown until the next key
+    pub message: Option<String>,
+    // Search of the pane shown
+    pub search: Option<PaneSearch>,
+    pub search_options: QueryOptions,
+    // Direction of the last search
+    backward: bool,
+    // Direction of a jump waiting for the search to find a match
+    pending: Option<bool>,
+    // Match jumped to last
+    found: Option<Entry>,
+    pub global_filter: Option<Filter>,
+    // Filters of service panes, by service name so that they outlive a removed service
+    pub filters: HashMap<String, Filter>,
+    pub timeline_filter: Option<Filter>,
+    // Time of the recording being played back, if it's a replay
+    pub clock: Option<Clock>,
+}
+
+impl App {
+    pub fn new(services: Vec<Service>, collectors: Vec<LogCollector>, theme: Theme) -> Self {
+        App {
+            views: vec![0; services.len()],
+            viewports: services.iter().map(|_| Viewport::default()).collect(),
+            timeline_viewport: Viewport::default(),
+            page: 0,
+            timeline: Timeline::new(collectors.len()),
+            service_timelines: collectors.iter().map(|_| Timeline::new(1)).collect(),
+            services,
+            collectors,
+            current: 0,
+            stream: None,
+            show_time: false,
+            expanded: false,
+            folded: false,
+            ansi: true,
+            mode: Mode::Service,
+            newest_first: false,
+            theme,
+            prompt: None,
+            message: None,
+            search: None,
+            search_options: QueryOptions::default(),
+            backward: false,
+            pending: None,
+            found: None,
+            global_filter: None,
+            filters: HashMap::new(),
+            timeline_filter: None,
+            clock: None,
+        }
+    }
+
+    pub fn with_filters(mut self, global: Option<Filter>, services: Vec<(String, Filter)>) -> Self {
+        self.global_filter = global.filter(|f| !f.is_empty());
+        self.filters = services
+            .into_iter()
+            .filter(|(_, f)| !f.is_empty())
+            .collect();
+        self
+    }
+
+    pub fn with_ansi(mut self, ansi: bool) -> Self {
+        self.ansi = ansi;
+        self
+    }
+
+    pub fn with_clock(mut self, clock: Option<Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    pub fn update(&mut self) {
+        self.timeline.update(&self.collectors);
+        for (i, timeline) in self.service_timelines.iter_mut().enumerate() {
+            timeline.update(&self.collectors[i..=i]);
+        }
+
+        // A search follows the user to another pane
+        let service = match self.mode {
+            Mode::Service => self.services.get(self.current).map(|s| &s.service_name),
+            Mode::Timeline => None,
+        };
+        let searches = match self.mode {
+            Mode::Service => 1,
+            Mode::Timeline => self.collectors.len(),
+        };
+        if let Some(search) = &self.search {
+            if search.service.as_ref() != service || search.searches.len() != searches {
+                let query = search.query().clone();
+                self.search = self.start_search(query);
+                self.found = None;
+            }
+        }
+        if self.pending.is_some() {
+            self.jump();
+        }
+    }
+
+    pub fn add_service(&mut self, service: Service, collector: LogCollector) {
+        self.services.push(service);
+        self.collectors.push(collector);
+        self.service_timelines.push(Timeline::new(1));
+        self.views.push(0);
+        self.viewports.push(Viewport::default());
+    }
+
+    pub fn update_service(&mut self, service: Service) {
+        if let Some(i) = self.index(&service.service_name) {
+            self.collectors[i].update(&service);
+            self.views[i] = self.views[i].min(service.replicas.len());
+            self.services[i] = service;
+        }
+    }
+
+    pub fn remove_service(&mut self, name: &str) {
+        if let Some(i) = self.index(name) {
+            self.services.remove(i);
+            self.collectors.remove(i);
+            self.service_timelines.remove(i);
+            self.views.remove(i);
+            self.viewports.remove(i);
+            // A prompt or search of the pane shown is about a service which is gone
+            if (self.mode == Mode::Service && i == self.current) || self.services.is_empty() {
+                self.prompt = None;
+                self.search = None;
+                self.found = None;
+                self.pending = None;
+            }
+            if i < self.current {
+                self.current -= 1;
+            } else if self.current >= self.services.len() {
+                self.current = self.services.len().saturating_sub(1);
+            }
+            // Timeline entries refer to collectors by index, so they are merged again
+            self.timeline = Timeline::new(self.collectors.len());
+            self.timeline_viewport.follow();
+            if let Some(search) = &mut self.search {
+                if search.service.is_none() {
+                    search.searches.remove(i);
+                    self.found = None;
+                }
+            }
+        }
+    }
+
+    fn index(&self, name: &str) -> Option<usize> {
+        self.services.iter().position(|s| s.service_name == name)
+    }
+
+    /// Replica shown in the selected tab of the current service, `None` for all of them.
+    pub fn replica(&self) -> Option<u32> {
+        match self.views.get(self.current) {
+            Some(0) | None => None,
+            Some(&n) => self.services[self.current]
+                .replicas
+                .get(n - 1)
+                .map(|r| r.number),
+        }
+    }
+
+    /// Filter of the pane shown in the current mode.
+    pub fn pane_filter(&self) -> Option<&Filter> {
+        match self.mode {
+            Mode::Service => self
+                .services
+                .get(self.current)
+                .and_then(|s| self.filters.get(&s.service_name)),
+            Mode::Timeline => self.timeline_filter.as_ref(),
+        }
+    }
+
+    fn filter(&self) -> impl Fn(&LogRecord, Format) -> bool {
+        let stream = self.stream;
+        let replica = match self.mode {
+            Mode::Service => self.replica(),
+            Mode::Timeline => None,
+        };
+        let filters: Vec<Filter> = self
+            .global_filter
+            .iter()
+            .chain(self.pane_filter())
+            .cloned()
+            .collect();
+        move |record, format| {
+            replica.is_none_or(|n| record.replica == n)
+                && record.matches(stream)
+                && filters
+                    .iter()
+                    .all(|filter| filter.matches_as(record, format))
+        }
+    }
+
+    /// Lines to show in the log pane of the current mode, oldest first.
+    pub fn lines(&self, height: usize) -> Vec<Line> {
+        match self.mode {
+            Mode::Service if self.services.is_empty() => vec![],
+            Mode::Service => {
+                let i = self.current;
+                let fetch = viewport::fetch(&self.collectors[i..=i], self.filter());
+                self.viewports[i].lines(self.service_timelines[i].entries(), fetch, height)
+            }
+            Mode::Timeline => {
+                let fetch = viewport::fetch(&self.collectors, self.filter());
+                self.timeline_viewport
+                    .lines(self.timeline.entries(), fetch, height)
+            }
+        }
+    }
+
+    pub fn is_following(&self) -> bool {
+        match self.mode {
+            Mode::Service => self
+                .viewports
+                .get(self.current)
+                .is_none_or(Viewport::is_following),
+            Mode::Timeline => self.timeline_viewport.is_following(),
+        }
+    }
+
+    // Runs a scroll action on the viewport of the current mode
+    fn scroll<R>(
+        &mut self,
+        action: impl FnOnce(&mut Viewport, &[Entry], &mut Fetch) -> R,
+    ) -> Option<R> {
+        let filter = self.filter();
+        match self.mode {
+            Mode::Service if self.services.is_empty() => None,
+            Mode::Service => {
+                let i = self.current;
+                let mut fetch = viewport::fetch(&self.collectors[i..=i], filter);
+                let entries = self.service_timelines[i].entries();
+                Some(action(&mut self.viewports[i], entries, &mut fetch))
+            }
+            Mode::Timeline => {
+                let mut fetch = viewport::fetch(&self.collectors, filter);
+                let entries = self.timeline.entries();
+                Some(action(&mut self.timeline_viewport, entries, &mut fetch))
+            }
+        }
+    }
+
+    fn up(&mut self, n: usize) {
+        let page = self.page;
+        let moved = self.scroll(|viewport, entries, fetch| viewport.up(entries, fetch, page, n));
+        // Scrolling past the oldest line loaded asks for older ones
+        if moved == Some(false) {
+            self.fetch_more();
+        }
+    }
+
+    fn down(&mut self, n: usize) {
+        self.scroll(|viewport, entries, fetch| viewport.down(entries, fetch, n));
+    }
+
+    /// Query of the search whose matches are highlighted.
+    pub fn query(&self) -> Option<&Query> {
+        self.search.as_ref().map(PaneSearch::query)
+    }
+
+    // Viewport of the pane shown in the current mode
+    fn viewport_mut(&mut self) -> Option<&mut Viewport> {
+        match self.mode {
+            Mode::Service => self.viewports.get_mut(self.current),
+            Mode::Timeline => Some(&mut self.timeline_viewport),
+        }
+    }
+
+    // Searches the collectors of the pane shown in the current mode
+    fn start_search(&self, query: Query) -> Option<PaneSearch> {
+        match self.mode {
+            Mode::Service => {
+                let service = self.services.get(self.current)?;
+                let collector = self.collectors.get(self.current)?;
+                Some(PaneSearch {
+                    service: Some(service.service_name.clone()),
+                    searches: vec![collector.search(query)],
+                })
+            }
+            Mode::Timeline if self.collectors.is_empty() => None,
+            Mode::Timeline => Some(PaneSearch {
+                service: None,
+                searches: self
+                    .collectors
+                    .iter()
+                    .map(|c| c.search(query.clone()))
+                    .collect(),
+            }),
+        }
+    }
+
+    fn open_prompt(&mut self, kind: PromptKind) {
+        let input = match kind {
+            PromptKind::Search { .. } => String::new(),
+            PromptKind::Filter => self
+                .pane_filter()
+                .map(|f| f.to_string())
+                .unwrap_or_default(),
+            PromptKind::GlobalFilter => self
+                .global_filter
+                .as_ref()
+                .map(|f| f.to_string())
+                .unwrap_or_default(),
+            PromptKind::Export => {
+                let name = match self.mode {
+                    Mode::Service if self.services.is_empty() => return,
+                    Mode::Service => self.services[self.current].service_name.as_str(),
+                    Mode::Timeline => "timeline",
+                };
+                format!(
+                    "decom-{}-{}.ndjson",
+                    name,
+                    Local::now().format("%Y%m%d-%H%M%S")
+                )
+            }
+        };
+        if self.mode == Mode::Service && self.services.is_empty() {
+            return;
+        }
+        let origin = match self.mode {
+            Mode::Service => self.viewports[self.current].clone(),
+            Mode::Timeline => self.timeline_viewport.clone(),
+        };
+        self.prompt = Some(Prompt {
+            kind,
+            input,
+            error: None,
+            origin,
+        });
+    }
+
+    // Replaces the filter the prompt edits, an empty expression removes it
+    fn set_filter(&mut self, kind: PromptKind, expr: &str) -> Result<(), Error> {
+        let filter = Some(Filter::parse(expr)?).filter(|f| !f.is_empty());
+        match (kind, self.mode) {
+            (PromptKind::GlobalFilter, _) => self.global_filter = filter,
+            (PromptKind::Filter, Mode::Timeline) => self.timeline_filter = filter,
+            (PromptKind::Filter, Mode::Service) => {
+                let name = match self.services.get(self.current) {
+                    Some(service) => service.service_name.clone(),
+                    None => return Ok(()),
+                };
+                match filter {
+                    Some(filter) => self.filters.insert(name, filter),
+                    None => self.filters.remove(&name),
+                };
+            }
+            (PromptKind::Search { .. }, _) | (PromptKind::Export, _) => {}
+        }
+        Ok(())
+    }
+
+    /// Writes the lines the current pane shows, through its filters, to the file. The format
+    /// goes by its extension, NDJSON if it has none known.
+    fn export(&self, path: &str) -> Result<usize, Error> {
+        let (collectors, entries) = match self.mode {
+            Mode::Service => {
+                let i = self.current;
+                (&self.collectors[i..=i], self.service_timelines[i].entries())
+            }
+            Mode::Timeline => (&self.collectors[..], self.timeline.entries()),
+        };
+        let path = Path::new(path);
+        let format = ExportFormat::from_path(path).unwrap_or(ExportFormat::Ndjson);
+        let mut exporter = Exporter::new(BufWriter::new(File::create(path)?), format)?;
+        let mut fetch = viewport::fetch(collectors, self.filter());
+        for chunk in entries.chunks(FETCH_LINES) {
+            for (entry, record) in chunk.iter().zip(fetch(chunk)) {
+                if let Some(record) = record {
+                    let collector = &collectors[entry.source];
+                    exporter.write(collector.service_name(), &record, collector.format())?;
+                }
+            }
+        }
+        let count = exporter.count();
+        exporter.finish()?;
+        Ok(count)
+    }
+
+    fn on_prompt_key(&mut self, key: KeyEvent) {
+        let mut prompt = match self.prompt.take() {
+            Some(prompt) => prompt,
+            None => return,
+        };
+        let control = key.modifiers.contains(KeyModifiers::CONTROL);
+        let backward = match prompt.kind {
+            PromptKind::Search { backward } => backward,
+            kind => {
+                match key.code {
+                    KeyCode::Esc => return,
+                    KeyCode::Enter if kind == PromptKind::Export => {
+                        match self.export(&prompt.input) {
+                            Ok(count) => {
+                                self.message =
+                                    Some(format!("exported {} lines to {}", count, prompt.input));
+                                return;
+                            }
+                            Err(e) => prompt.error = Some(e.to_string()),
+                        }
+                    }
+                    KeyCode::Enter => match self.set_filter(kind, &prompt.input) {
+                        Ok(()) => return,
+                        Err(e) => prompt.error = Some(e.to_string()),
+                    },
+                    KeyCode::Backspace => {
+                        prompt.input.pop();
+                        prompt.error = None;
+                    }
+                    KeyCode::Char(c) if !control => {
+                        prompt.input.push(c);
+                        prompt.error = None;
+                    }
+                    _ => {}
+                }
+                self.prompt = Some(prompt);
+                return;
+            }
+        };
+
+        match key.code {
+            KeyCode::Esc => {
+                if let Some(viewport) = self.viewport_mut() {
+                    *viewport = prompt.origin;
+                }
+                self.search = None;
+                self.found = None;
+                self.pending = None;
+                return;
+            }
+            KeyCode::Enter => return,
+            KeyCode::Backspace => {
+                prompt.input.pop();
+            }
+            KeyCode::Char('r') if control => {
+                self.search_options.regex = !self.search_options.regex;
+            }
+            KeyCode::Char('s') if control => {
+                self.search_options.ignore_case = !self.search_options.ignore_case;
+            }
+            KeyCode::Char(c) if !control => prompt.input.push(c),
+            _ => {
+                self.prompt = Some(prompt);
+                return;
+            }
+        }
+
+        // Searches as the pattern is typed, from where the prompt was opened
+        if let Some(viewport) = self.viewport_mut() {
+            *viewport = prompt.origin.clone();
+        }
+        self.backward = backward;
+        self.found = None;
+        self.pending = None;
+        self.search = None;
+        prompt.error = None;
+        if !prompt.input.is_empty() {
+            match Query::new(&prompt.input, self.search_options) {
+                Ok(query) => {
+                    self.search = self.start_search(query);
+                    self.pending = Some(self.backward);
+                }
+                Err(e) => prompt.error = Some(e.to_string()),
+            }
+        }
+        self.prompt = Some(prompt);
+    }
+
+    // Scrolls to the next match in the direction, once the search has found it
+    fn jump(&mut self) {
+        let backward = match self.pending {
+            Some(backward) => backward,
+            None => return,
+        };
+        let search = match &self.search {
+            Some(search) => search,
+            None => return,
+        };
+        let (collectors, entries, viewport) = match self.mode {
+            Mode::Service if self.services.is_empty() => return,
+            Mode::Service => {
+                let i = self.current;
+                let entries = self.service_timelines[i].entries();
+                (&self.collectors[i..=i], entries, &self.viewports[i])
+            }
+            Mode::Timeline => (
+                &self.collectors[..],
+                self.timeline.entries(),
+                &self.timeline_viewport,
+            ),
+        };
+        let start = match self.found {
+            Some(found) if backward => entries.partition_point(|entry| *entry < found),
+            Some(found) => entries.partition_point(|entry| *entry <= found),
+            None if backward => viewport.position(entries),
+            None => viewport.position(entries).saturating_sub(self.page),
+        };
+
+        let filter = self.filter();
+        let matches = |entry: &&Entry| {
+            let collector = &collectors[entry.source];
+            search.contains(entry)
+                && collector
+                    .get(entry.seq)
+                    .is_some_and(|r| filter(&r, collector.format()))
+        };
+        let found = if backward {
+            entries[..start].iter().rev().find(matches)
+        } else {
+            entries[start..].iter().find(matches)
+        };
+        match found.copied() {
+            Some(entry) => {
+                if let Some(viewport) = self.viewport_mut() {
+                    viewport.scroll_to(entry);
+                }
+                self.found = Some(entry);
+                self.pending = None;
+            }
+            None if search.is_done() => self.pending = None,
+            None => {}
+        }
+    }
+
+    fn fetch_more(&mut self) {
+        match self.mode {
+            Mode::Service if !self.collectors.is_empty() => {
+                self.collectors[self.current].fetch_more(FETCH_LINES);
+            }
+            Mode::Service => {}
+            Mode::Timeline => {
+                for collector in self.collectors.iter_mut() {
+                    collector.fetch_more(FETCH_LINES);
+                }
+            }
+        }
+    }
+
+    /// Handles a key press, returns `false` when the app should quit.
+    pub fn on_key(&mut self, key: KeyEvent) -> bool {
+        self.message = None;
+        if self.prompt.is_some() {
+            self.on_prompt_key(key);
+            return true;
+        }
+        let max = self.services.len() as i32 - 1;
+        match key.code {
+            KeyCode::Char('/') => self.open_prompt(PromptKind::Search { backward: false }),
+            KeyCode::Char('?') => self.open_prompt(PromptKind::Search { backward: true }),
+            KeyCode::Char('f') => self.open_prompt(PromptKind::Filter),
+            KeyCode::Char('F') => self.open_prompt(PromptKind::GlobalFilter),
+            KeyCode::Char('s') => self.open_prompt(PromptKind::Export),
+            KeyCode::Char('n') if self.search.is_some() => {
+                self.pending = Some(self.backward);
+                self.jump();
+            }
+            KeyCode::Char('N') if self.search.is_some() => {
+                self.pending = Some(!self.backward);
+                self.jump();
+            }
+            KeyCode::Esc => {
+                self.search = None;
+                self.found = None;
+                self.pending = None;
+            }
+            KeyCode::Char('h') => {
+                self.current = clamp(self.current as i32, -1, max) as usize;
+            }
+            KeyCode::Char('l') => {
+                self.current = clamp(self.current as i32, 1, max) as usize;
+            }
+            KeyCode::Char('T') => {
+                self.mode = match self.mode {
+                    Mode::Service => Mode::Timeline,
+                    Mode::Timeline => Mode::Service,
+                };
+            }
+            KeyCode::Char('k') | KeyCode::Up => self.up(1),
+            KeyCode::Char('j') | KeyCode::Down => self.down(1),
+            KeyCode::PageUp => self.up(self.page),
+            KeyCode::PageDown => self.down(self.page),
+            KeyCode::Char('g') | KeyCode::Home => {
+                let page = self.page;
+                self.scroll(|viewport, entries, fetch| viewport.top(entries, fetch, page));
+            }
+            KeyCode::Char('G') | KeyCode::End => {
+                self.scroll(|viewport, _, _| viewport.follow());
+            }
+            KeyCode::Char('m') => self.fetch_more(),
+            KeyCode::Char('o') => {
+                self.newest_first = !self.newest_first;
+            }
+            KeyCode::Char('t') => {
+                self.show_time = !self.show_time;
+            }
+            KeyCode::Char('x') => {
+                self.expanded = !self.expanded;
+            }
+            KeyCode::Char('z') => {
+                self.folded = !self.folded;
+            }
+            KeyCode::Char('c') => {
+                self.ansi = !self.ansi;
+            }
+            KeyCode::Char('e') => {
+                self.stream = match self.stream {
+                    None => Some(LogStream::Stderr),
+                    Some(LogStream::Stderr) => Some(LogStream::Stdout),
+                    Some(LogStream::Stdout) | Some(LogStream::Event) => None,
+                };
+            }
+            KeyCode::Char('>') | KeyCode::Char('<') => {
+                if let Some(clock) = &self.clock {
+                    let factor = if key.code == KeyCode::Char('>') {
+                        2.0
+                    } else {
+                        0.5
+                    };
+                    clock.set_speed(clock.speed() * factor);
+                }
+            }
+            KeyCode::Char(' ') => {
+                if let Some(clock) = &self.clock {
+                    clock.set_paused(!clock.is_paused());
+                }
+            }
+            KeyCode::Tab | KeyCode::BackTab if self.services.is_empty() => {}
+            KeyCode::Tab => {
+                let tabs = self.services[self.current].replicas.len() + 1;
+                let view = &mut self.views[self.current];
+                *view = (*view + 1) % tabs;
+            }
+            KeyCode::BackTab => {
+                let tabs = self.services[self.current].replicas.len() + 1;
+                let view = &mut self.views[self.current];
+                *view = (*view + tabs - 1) % tabs;
+            }
+            KeyCode::Char('q') => return false,
+            _ => {}
+        }
+        true
+    }
+}
+
+fn clamp(n: i32, d: i32, max: i32) -> i32 {
+    let mut n = n + d;
+    if n > max {
+        n = max;
+    }
+    // Also covers `max` of -1 when there are no services
+    if n < 0 {
+        n = 0;
+    }
+    n
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+    use decom_core::{LogStore, Multiline, Recording, Replay, Replica, Retention, RingBuffer};
+    use std::{sync::Arc, time::Duration};
+
+    fn service(name: &str) -> Service {
+        Service {
+            service_name: name.into(),
+            replicas: vec![Replica {
+                number: 1,
+                container_name: format!("app_{}_1", name),
+                container_id: name.into(),
+                state: "running".into(),
+            }],
+            format: Format::Auto,
+            multiline: Multiline::Auto,
+            retention: Retention::default(),
+        }
+    }
+
+    fn key(code: KeyCode) -> KeyEvent {
+        KeyEvent::new(code, KeyModifiers::NONE)
+    }
+
+    // App showing the services, whose lines are written one per second in turn
+    fn app(names: &[&str], lines: u32) -> App {
+        // An empty recording, as collectors aren't started
+        let recording = Recording::read(&b"DECOM\x01\x00\x00"[..]).unwrap();
+        let backend = Arc::new(Replay::new(recording));
+        let services: Vec<Service> = names.iter().map(|name| service(name)).collect();
+        let collectors = services
+            .iter()
+            .enumerate()
+            .map(|(i, s)| {
+                let mut store = RingBuffer::new(Retention::default());
+                for n in 0..lines {
+                    let secs = (n as usize * names.len() + i) as i64;
+                    let record = LogRecord {
+                        seq: 0,
+                        timestamp: Utc.timestamp_opt(secs, 0).unwrap(),
+                        stream: LogStream::Stdout,
+                        container: Arc::from(s.replicas[0].container_name.as_str()),
+                        replica: 1,
+                        raw: format!("{} {}", s.service_name, n),
+                    };
+                    store.push(record).unwrap();
+                }
+                LogCollector::with_store(backend.clone(), s, Box::new(store))
+            })
+            .collect();
+        let mut app = App::new(services, collectors, Theme::Dark);
+        app.update();
+        app
+    }
+
+    fn raws(app: &App) -> Vec<String> {
+        let lines = app.lines(app.page);
+        lines.into_iter().map(|(_, record)| record.raw).collect()
+    }
+
+    #[tokio::test]
+    async fn test_remove_service() {
+        let mut app = app(&["db", "web"], 0);
+
+        // Removing another service keeps the pane shown
+        app.current = 1;
+        app.on_key(key(KeyCode::Char('/')));
+        app.on_key(key(KeyCode::Char('x')));
+        app.remove_service("db");
+        assert_eq!(app.current, 0);
+        assert!(app.prompt.is_some());
+        assert!(app.search.is_some());
+
+        app.remove_service("web");
+        assert!(app.prompt.is_none());
+        assert!(app.search.is_none());
+        for code in [KeyCode::Char('/'), KeyCode::Char('x'), KeyCode::Esc] {
+            app.on_key(key(code));
+        }
+        assert!(app.lines(10).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_search_timeline() {
+        let mut app = app(&["db", "web"], 10);
+        app.page = 2;
+        app.on_key(key(KeyCode::Char('T')));
+        for code in [KeyCode::Char('?'), KeyCode::Char('2'), KeyCode::Enter] {
+            app.on_key(key(code));
+        }
+        while app.pending.is_some() || app.search.as_ref().is_some_and(|s| !s.is_done()) {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            app.update();
+        }
+        assert_eq!(app.search.as_ref().unwrap().len(), 2);
+        assert_eq!(raws(&app), ["db 2", "web 2"]);
+
+        // Matches of every service are gone through
+        app.on_key(key(KeyCode::Char('n')));
+        assert_eq!(raws(&app), ["web 1", "db 2"]);
+    }
+}
@@ -0,0 +1,610 @@
+use chrono::{DateTime, Duration, Utc};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use decom_core::{
+    store::parse_size, Backlog, Error, ExportFormat, Filter, Format, LogRecord, Multiline,
+    ProjectOptions, Retention, Service,
+};
+use std::path::PathBuf;
+
+/// Decompose docker-compose logs and organize them.
+#[derive(Debug, Parser)]
+#[command(name = "decom", version)]
+pub struct Cli {
+    /// Compose configuration files
+    #[arg(short = 'f', long = "file", value_name = "FILE", global = true)]
+    pub files: Vec<PathBuf>,
+
+    /// Project name
+    #[arg(short = 'p', long, global = true)]
+    pub project_name: Option<String>,
+
+    /// Alternate working directory of the project
+    #[arg(long, value_name = "PATH", global = true)]
+    pub project_directory: Option<PathBuf>,
+
+    /// Show logs since a timestamp (e.g. 2021-05-01T10:00:00Z) or a relative time (e.g. 42m, 1h30m)
+    #[arg(long, value_name = "TIME", value_parser = parse_time, global = true)]
+    pub since: Option<DateTime<Utc>>,
+
+    /// Show logs before a timestamp or a relative time, without following new ones
+    #[arg(long, value_name = "TIME", value_parser = parse_time, global = true)]
+    pub until: Option<DateTime<Utc>>,
+
+    /// Number of lines to load from the end of the logs of each container, or "all". 1000 by
+    /// default, and all of them for an export. Older lines are loaded on demand
+    #[arg(long, value_name = "N", value_parser = parse_tail, global = true)]
+    pub tail: Option<Tail>,
+
+    /// Show only lines matching the expression, e.g. 'level>=warn and not msg~"healthcheck"'
+    #[arg(long, value_name = "EXPR", value_parser = parse_filter, global = true)]
+    pub filter: Option<Filter>,
+
+    /// Show only lines of the service matching the expression, e.g. 'web=stream=stderr'
+    #[arg(
+        long,
+        value_name = "SERVICE=EXPR",
+        value_parser = parse_service_filter,
+        global = true
+    )]
+    pub service_filter: Vec<(String, Filter)>,
+
+    /// Format of the service's log lines: auto, json, logfmt, access, plain or raw, e.g.
+    /// 'nginx=access'. Overrides the service's `decom.format` label
+    #[arg(
+        long,
+        value_name = "SERVICE=FORMAT",
+        value_parser = parse_service_format,
+        global = true
+    )]
+    pub format: Vec<(String, Format)>,
+
+    /// How lines of the service are joined into records: auto, off or a pattern of the first
+    /// line of each, e.g. 'api=^\d{4}-'. Overrides the service's `decom.multiline` label
+    #[arg(
+        long,
+        value_name = "SERVICE=MODE",
+        value_parser = parse_service_multiline,
+        global = true
+    )]
+    pub multiline: Vec<(String, Multiline)>,
+
+    /// Number of lines held in memory per service, or "unbounded", for all services or for one
+    /// with 'SERVICE=N'. Overrides the service's `decom.retention` label. Older lines are dropped
+    #[arg(
+        long,
+        value_name = "[SERVICE=]N",
+        value_parser = parse_retention_lines,
+        global = true
+    )]
+    pub retention_lines: Vec<(Option<String>, Option<usize>)>,
+
+    /// Size of the lines held in memory per service such as 32MiB, or "unbounded", for all
+    /// services or for one with 'SERVICE=SIZE'. Overrides the service's `decom.retention` label
+    #[arg(
+        long,
+        value_name = "[SERVICE=]SIZE",
+        value_parser = parse_retention_bytes,
+        global = true
+    )]
+    pub retention_bytes: Vec<(Option<String>, Option<usize>)>,
+
+    /// Strip colours and other escape sequences written by containers instead of showing them
+    #[arg(long, global = true)]
+    pub no_ansi: bool,
+
+    /// Write a merged stream to stdout instead of running the terminal UI, until every container
+    /// has stopped. Exits with the first non-zero exit code of them
+    #[arg(long, global = true)]
+    pub no_tui: bool,
+
+    /// Colour theme
+    #[arg(long, value_enum, default_value_t = Theme::Dark, global = true)]
+    pub theme: Theme,
+
+    /// Spill logs to a session directory instead of holding them in memory
+    #[arg(long, global = true)]
+    pub disk: bool,
+
+    /// Record the logs and the states of the containers to a file, to browse them later with
+    /// `decom replay FILE`
+    #[arg(long, value_name = "FILE", global = true)]
+    pub record: Option<PathBuf>,
+
+    /// Services to follow, all of them if none given
+    #[arg(value_name = "SERVICE")]
+    pub services: Vec<String>,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Write the logs of services to a file and exit, e.g. to attach to a bug report.
+    /// Everything within --since and --until passing the filters is written
+    Export(Export),
+    /// Browse a session recorded with --record as it happened, without Docker.
+    /// `<` and `>` change the speed and space pauses it
+    Replay(Replay),
+}
+
+#[derive(Debug, Args)]
+pub struct Export {
+    /// File to write to, stdout if not given
+    #[arg(short = 'o', long, value_name = "FILE")]
+    pub output: Option<PathBuf>,
+
+    /// Format of the file: ndjson, csv or text. Goes by the extension of the file if not given,
+    /// NDJSON otherwise
+    #[arg(long = "type", value_name = "FORMAT")]
+    pub kind: Option<ExportFormat>,
+
+    /// Services to export, all of them if none given
+    #[arg(value_name = "SERVICE")]
+    pub services: Vec<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct Replay {
+    /// Recording to play back
+    #[arg(value_name = "FILE")]
+    pub path: PathBuf,
+
+    /// Speed of playback, e.g. 2 for twice as fast as recorded or 0.5 for half as fast, up to 64
+    #[arg(long, value_name = "SPEED", default_value_t = 1.0, value_parser = parse_speed)]
+    pub speed: f64,
+
+    /// Services to show, all of them if none given
+    #[arg(value_name = "SERVICE")]
+    pub services: Vec<String>,
+}
+
+impl Export {
+    pub fn format(&self) -> ExportFormat {
+        self.kind
+            .or_else(|| self.output.as_deref().and_then(ExportFormat::from_path))
+            .unwrap_or(ExportFormat::Ndjson)
+    }
+}
+
+impl Cli {
+    pub fn project_options(&self) -> ProjectOptions {
+        ProjectOptions {
+            files: self.files.clone(),
+            project_name: self.project_name.clone(),
+            project_directory: self.project_directory.clone(),
+        }
+        .with_env()
+    }
+
+    /// What to collect of the logs. An export takes all of them up to now, unless bounded.
+    pub fn backlog(&self) -> Backlog {
+        match self.command {
+            Some(Command::Export(_)) => Backlog {
+                since: self.since,
+                until: Some(self.until.unwrap_or_else(Utc::now)),
+                tail: match self.tail {
+                    Some(Tail::Lines(n)) => Some(n),
+                    Some(Tail::All) | None => None,
+                },
+            },
+            _ => Backlog {
+                since: self.since,
+                until: self.until,
+                tail: match self.tail.unwrap_or(DEFAULT_TAIL) {
+                    Tail::All => None,
+                    Tail::Lines(n) => Some(n),
+                },
+            },
+        }
+    }
+
+    pub fn selects(&self, service_name: &str) -> bool {
+        let services = match &self.command {
+            Some(Command::Export(export)) => &export.services,
+            Some(Command::Replay(replay)) => &replay.services,
+            None => &self.services,
+        };
+        services.is_empty() || services.iter().any(|s| s == service_name)
+    }
+
+    /// Format to parse the service's lines in, given on the command line or by its label.
+    pub fn format(&self, service: &Service) -> Format {
+        self.format
+            .iter()
+            .rev()
+            .find(|(name, _)| *name == service.service_name)
+            .map_or(service.format, |(_, format)| *format)
+    }
+
+    /// How to join the service's lines, given on the command line or by its label.
+    pub fn multiline(&self, service: &Service) -> Multiline {
+        self.multiline
+            .iter()
+            .rev()
+            .find(|(name, _)| *name == service.service_name)
+            .map_or_else(
+                || service.multiline.clone(),
+                |(_, multiline)| multiline.clone(),
+            )
+    }
+
+    /// How much of the service's log to hold in memory, given on the command line or by its
+    /// label. A limit given for the service wins over one given for all of them.
+    pub fn retention(&self, service: &Service) -> Retention {
+        let limit = |limits: &[(Option<String>, Option<usize>)]| {
+            let name = Some(service.service_name.as_str());
+            limits
+                .iter()
+                .rev()
+                .find(|(service, _)| service.as_deref() == name)
+                .or_else(|| limits.iter().rev().find(|(service, _)| service.is_none()))
+                .map(|(_, limit)| *limit)
+        };
+        Retention {
+            max_lines: limit(&self.retention_lines).unwrap_or(service.retention.max_lines),
+            max_bytes: limit(&self.retention_bytes).unwrap_or(service.retention.max_bytes),
+        }
+    }
+
+    /// Whether the record of the service passes the global filter and the service's ones.
+    pub fn shows(&self, service_name: &str, record: &LogRecord, format: Format) -> bool {
+        self.filter
+            .iter()
+            .all(|filter| filter.matches_as(record, format))
+            && self
+                .service_filter
+                .iter()
+                .filter(|(name, _)| name == service_name)
+                .all(|(_, filter)| filter.matches_as(record, format))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum Theme {
+    /// For dark terminal backgrounds
+    Dark,
+    /// For light terminal backgrounds
+    Light,
+    /// No colours
+    Mono,
+}
+
+// Lines loaded of each container when attaching, unless told otherwise
+const DEFAULT_TAIL: Tail = Tail::Lines(1000);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Tail {
+    All,
+    Lines(usize),
+}
+
+fn parse_tail(value: &str) -> Result<Tail, String> {
+    match value {
+        "all" => Ok(Tail::All),
+        n => n
+            .parse()
+            .map(Tail::Lines)
+            .map_err(|_| format!("invalid number of lines: {}", n)),
+    }
+}
+
+fn parse_speed(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(speed) if speed > 0.0 => Ok(speed),
+        _ => Err(format!("invalid speed: {}", value)),
+    }
+}
+
+fn parse_filter(value: &str) -> Result<Filter, String> {
+    Filter::parse(value).map_err(|e| e.to_string())
+}
+
+fn parse_service_filter(value: &str) -> Result<(String, Filter), String> {
+    match split_service(value) {
+        Some((service, expr)) => Ok((service, parse_filter(expr)?)),
+        None => Err(format!("expected SERVICE=EXPR: {}", value)),
+    }
+}
+
+fn parse_service_format(value: &str) -> Result<(String, Format), String> {
+    match split_service(value) {
+        Some((service, format)) => Ok((service, format.parse()?)),
+        None => Err(format!("expected SERVICE=FORMAT: {}", value)),
+    }
+}
+
+fn parse_service_multiline(value: &str) -> Result<(String, Multiline), String> {
+    match split_service(value) {
+        Some((service, mode)) => Ok((service, mode.parse().map_err(|e: Error| e.to_string())?)),
+        None => Err(format!("expected SERVICE=MODE: {}", value)),
+    }
+}
+
+fn parse_retention_lines(value: &str) -> Result<(Option<String>, Option<usize>), String> {
+    parse_limit(value, |limit| limit.parse().ok())
+}
+
+fn parse_retention_bytes(value: &str) -> Result<(Option<String>, Option<usize>), String> {
+    parse_limit(value, parse_size)
+}
+
+// Parses `[SERVICE=]LIMIT`, where the limit is `None` when unbounded
+fn parse_limit(
+    value: &str,
+    parse: impl Fn(&str) -> Option<usize>,
+) -> Result<(Option<String>, Option<usize>), String> {
+    let (service, limit) = match split_service(value) {
+        Some((service, limit)) => (Some(service), limit),
+        None => (None, value),
+    };
+    match limit {
+        "unbounded" => Ok((service, None)),
+        limit => match parse(limit) {
+            Some(n) => Ok((service, Some(n))),
+            None => Err(format!("invalid limit: {}", value)),
+        },
+    }
+}
+
+// Splits `SERVICE=VALUE` at the first `=`, if the service is a valid name
+fn split_service(value: &str) -> Option<(String, &str)> {
+    let (service, rest) = value.split_once('=')?;
+    let valid = !service.is_empty()
+        && service
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "._-".contains(c));
+    if valid {
+        Some((service.to_string(), rest))
+    } else {
+        None
+    }
+}
+
+fn parse_time(value: &str) -> Result<DateTime<Utc>, String> {
+    if let Ok(timestamp) = DateTime::parse_from_rfc3339(value) {
+        return Ok(timestamp.with_timezone(&Utc));
+    }
+    parse_duration(value)
+        .map(|duration| Utc::now() - duration)
+        .ok_or_else(|| format!("invalid time: {}", value))
+}
+
+/// Parses a relative time such as `90s`, `42m` or `1h30m`.
+fn parse_duration(value: &str) -> Option<Duration> {
+    let mut total = Duration::zero();
+    let mut digits = String::new();
+    for c in value.chars() {
+        if c.is_ascii_digit() {
+            digits.push(c);
+            continue;
+        }
+        let n: i64 = digits.parse().ok()?;
+        digits.clear();
+        total += match c {
+            's' => Duration::seconds(n),
+            'm' => Duration::minutes(n),
+            'h' => Duration::hours(n),
+            'd' => Duration::days(n),
+            _ => return None,
+        };
+    }
+    if digits.is_empty() && !value.is_empty() {
+        Some(total)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("90s"), Some(Duration::seconds(90)));
+        assert_eq!(parse_duration("1h30m"), Some(Duration::minutes(90)));
+        assert_eq!(parse_duration("2d"), Some(Duration::days(2)));
+        assert_eq!(parse_duration("10"), None);
+        assert_eq!(parse_duration("5w"), None);
+        assert_eq!(parse_duration(""), None);
+    }
+
+    #[test]
+    fn test_parse() {
+        let cli = Cli::try_parse_from([
+            "decom",
+            "-f",
+            "a.yml",
+            "--file=b.yml",
+            "-p",
+            "app",
+            "--since",
+            "2021-05-01T10:00:00Z",
+            "--tail",
+            "100",
+            "web",
+            "db",
+        ])
+        .unwrap();
+        assert_eq!(
+            cli.files,
+            vec![PathBuf::from("a.yml"), PathBuf::from("b.yml")]
+        );
+        assert_eq!(cli.project_name.as_deref(), Some("app"));
+        assert_eq!(cli.since.unwrap().to_rfc3339(), "2021-05-01T10:00:00+00:00");
+        assert_eq!(cli.tail, Some(Tail::Lines(100)));
+        assert_eq!(cli.until, None);
+        assert_eq!(cli.theme, Theme::Dark);
+        assert!(cli.selects("web") && !cli.selects("cache"));
+    }
+
+    #[test]
+    fn test_parse_tail() {
+        let cli = Cli::try_parse_from(["decom"]).unwrap();
+        assert_eq!(cli.backlog().tail, Some(1000));
+        let cli = Cli::try_parse_from(["decom", "--tail", "all"]).unwrap();
+        assert_eq!(cli.backlog().tail, None);
+        assert!(Cli::try_parse_from(["decom", "--tail", "many"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_filters() {
+        let cli = Cli::try_parse_from([
+            "decom",
+            "--filter",
+            "not msg~healthcheck",
+            "--service-filter",
+            "web=level>=warn",
+        ])
+        .unwrap();
+        assert_eq!(
+            cli.filter.as_ref().map(Filter::as_str),
+            Some("not msg~healthcheck")
+        );
+        assert_eq!(cli.service_filter[0].0, "web");
+        assert_eq!(cli.service_filter[0].1.as_str(), "level>=warn");
+
+        assert!(Cli::try_parse_from(["decom", "--filter", "level>=loud"]).is_err());
+        assert!(Cli::try_parse_from(["decom", "--service-filter", "level>=warn"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_export() {
+        let cli = Cli::try_parse_from([
+            "decom",
+            "export",
+            "--since",
+            "1h",
+            "--filter",
+            "level>=warn",
+            "-o",
+            "bug.csv",
+            "web",
+        ])
+        .unwrap();
+        let export = match &cli.command {
+            Some(Command::Export(export)) => export,
+            _ => panic!("no export"),
+        };
+        assert_eq!(export.format(), ExportFormat::Csv);
+        assert!(cli.filter.is_some());
+        assert!(cli.selects("web") && !cli.selects("db"));
+        let backlog = cli.backlog();
+        assert!(backlog.since.is_some() && backlog.until.is_some());
+        assert_eq!(backlog.tail, None);
+
+        let cli =
+            Cli::try_parse_from(["decom", "export", "--type", "text", "-o", "a.csv"]).unwrap();
+        match &cli.command {
+            Some(Command::Export(export)) => assert_eq!(export.format(), ExportFormat::Text),
+            _ => panic!("no export"),
+        }
+        assert!(Cli::try_parse_from(["decom", "export", "--type", "xml"]).is_err());
+
+        // Options of the top level also come after the subcommand
+        let cli = Cli::try_parse_from(["decom", "export", "--tail", "50", "web"]).unwrap();
+        assert_eq!(cli.backlog().tail, Some(50));
+        assert!(cli.selects("web"));
+    }
+
+    #[test]
+    fn test_parse_replay() {
+        let cli = Cli::try_parse_from([
+            "decom",
+            "replay",
+            "--speed",
+            "4",
+            "--filter",
+            "level>=warn",
+            "crash.decom",
+            "web",
+        ])
+        .unwrap();
+        match &cli.command {
+            Some(Command::Replay(replay)) => {
+                assert_eq!(replay.path, PathBuf::from("crash.decom"));
+                assert_eq!(replay.speed, 4.0);
+            }
+            _ => panic!("no replay"),
+        }
+        assert!(cli.selects("web") && !cli.selects("db"));
+        assert_eq!(cli.backlog().tail, Some(1000));
+
+        let cli = Cli::try_parse_from([
+            "decom",
+            "replay",
+            "crash.decom",
+            "--theme",
+            "light",
+            "--no-ansi",
+        ])
+        .unwrap();
+        assert_eq!(cli.theme, Theme::Light);
+        assert!(cli.no_ansi);
+
+        let cli = Cli::try_parse_from(["decom", "--record", "crash.decom"]).unwrap();
+        assert_eq!(cli.record, Some(PathBuf::from("crash.decom")));
+        assert!(Cli::try_parse_from(["decom", "replay", "--speed", "0", "a.decom"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_formats() {
+        let cli = Cli::try_parse_from([
+            "decom",
+            "--format",
+            "proxy=nginx",
+            "--format",
+            "api=logfmt",
+            "--multiline",
+            "api=^\\d{4}-",
+            "--multiline",
+            "proxy=off",
+        ])
+        .unwrap();
+        let service = |name: &str, format| Service {
+            service_name: name.into(),
+            replicas: vec![],
+            format,
+            multiline: Multiline::Auto,
+            retention: Retention::default(),
+        };
+        assert_eq!(cli.format(&service("proxy", Format::Auto)), Format::Access);
+        assert_eq!(cli.format(&service("api", Format::Json)), Format::Logfmt);
+        assert_eq!(cli.format(&service("db", Format::Plain)), Format::Plain);
+
+        assert!(Cli::try_parse_from(["decom", "--format", "api=yaml"]).is_err());
+
+        let limits = Cli::try_parse_from([
+            "decom",
+            "--retention-lines",
+            "5000",
+            "--retention-lines",
+            "db=unbounded",
+            "--retention-bytes",
+            "api=32MiB",
+        ])
+        .unwrap();
+        let mut api = service("api", Format::Auto);
+        api.retention.max_lines = Some(100);
+        let retention = limits.retention(&api);
+        assert_eq!(retention.max_lines, Some(5000));
+        assert_eq!(retention.max_bytes, Some(32 << 20));
+        let retention = limits.retention(&service("db", Format::Auto));
+        assert_eq!(retention.max_lines, None);
+        assert_eq!(retention.max_bytes, Retention::default().max_bytes);
+        assert!(Cli::try_parse_from(["decom", "--retention-bytes", "lots"]).is_err());
+        assert!(Cli::try_parse_from(["decom", "--format", "logfmt"]).is_err());
+
+        assert_eq!(
+            cli.multiline(&service("api", Format::Auto)).to_string(),
+            "^\\d{4}-"
+        );
+        assert_eq!(
+            cli.multiline(&service("proxy", Format::Auto)),
+            Multiline::Off
+        );
+        assert_eq!(cli.multiline(&service("db", Format::Auto)), Multiline::Auto);
+        assert!(Cli::try_parse_from(["decom", "--multiline", "api=("]).is_err());
+    }
+}
@@ -0,0 +1,48 @@
+use crate::cli::{Cli, Export};
+use decom_core::{Exporter, LogCollector, LogRecord};
+use std::{
+    error::Error,
+    fs::File,
+    io::{self, BufWriter, Write},
+    time::Duration,
+};
+
+/// Waits for the collectors to store the logs of the backlog, then writes the records passing
+/// the filters in the order they were written.
+pub async fn run(
+    cli: &Cli,
+    export: &Export,
+    collectors: Vec<LogCollector>,
+) -> Result<(), Box<dyn Error>> {
+    while !collectors.iter().all(LogCollector::is_finished) {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+
+    let mut records: Vec<(usize, LogRecord)> = vec![];
+    for (i, collector) in collectors.iter().enumerate() {
+        records.extend(
+            collector
+                .since(collector.first_seq())
+                .into_iter()
+                .filter(|record| cli.shows(collector.service_name(), record, collector.format()))
+                .map(|record| (i, record)),
+        );
+    }
+    records.sort_by_key(|(_, record)| record.timestamp);
+
+    let out: Box<dyn Write> = match &export.output {
+        Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+        None => Box::new(BufWriter::new(io::stdout())),
+    };
+    let mut exporter = Exporter::new(out, export.format())?;
+    for (i, record) in &records {
+        let collector = &collectors[*i];
+        exporter.write(collector.service_name(), record, collector.format())?;
+    }
+    let count = exporter.count();
+    exporter.finish()?;
+    if let Some(path) = &export.output {
+        eprintln!("decom: exported {} records to {}", count, path.display());
+    }
+    Ok(())
+}
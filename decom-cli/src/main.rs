@@ -1,24 +1,36 @@
+mod app;
+mod cli;
+mod export;
+mod stream;
+mod ui;
+mod viewport;
+
+use app::App;
+use clap::Parser;
+use cli::{Cli, Command};
 use crossterm::{
-    event::{self, Event as CEvent, KeyCode},
+    event::{self, Event as CEvent},
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
-// use futures::{stream, StreamExt};
-use decom_core::{docker_compose, LogCollector};
+use decom_core::{
+    backend, docker_compose,
+    store::{self, SegmentedStore, DEFAULT_SEGMENT_BYTES},
+    Backend, Discovery, LogCollector, Project, Recorder, Recording, Replay, Retention, RingBuffer,
+    Service, ServiceEvent,
+};
 use std::{
+    env,
     error::Error,
-    io,
+    io, process,
     result::Result,
-    sync::mpsc,
+    sync::{mpsc, Arc},
     thread,
     time::{Duration, Instant},
 };
-use tui::{
-    backend::CrosstermBackend,
-    text::Spans,
-    widgets::{Block, Borders, Paragraph, Wrap},
-    Terminal,
-};
+use tui::{backend::CrosstermBackend, Terminal};
+
+const DISCOVERY_INTERVAL: Duration = Duration::from_secs(2);
 
 enum Event<I> {
     Input(I),
@@ -26,7 +38,120 @@ enum Event<I> {
 }
 
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn Error>> {
+async fn main() {
+    if let Err(e) = run().await {
+        // The terminal may have been set up already
+        let _ = disable_raw_mode();
+        let _ = execute!(io::stdout(), LeaveAlternateScreen);
+        eprintln!("decom: {}", e);
+        process::exit(1);
+    }
+}
+
+async fn run() -> Result<(), Box<dyn Error>> {
+    let cli = Cli::parse();
+    // A replay plays a recording back in place of Docker
+    let replay = match &cli.command {
+        Some(Command::Replay(args)) => {
+            let replay = Replay::new(Recording::open(&args.path)?);
+            replay.clock().set_speed(args.speed);
+            Some(replay)
+        }
+        _ => None,
+    };
+    let project = match &replay {
+        Some(replay) => Project {
+            name: replay.recording().project().to_string(),
+            directory: env::current_dir()?,
+            files: vec![],
+        },
+        None => Project::discover(&cli.project_options(), &env::current_dir()?)?,
+    };
+    let session = if cli.disk {
+        Some(store::session_dir(&project.name))
+    } else {
+        None
+    };
+    let mut docker: Arc<dyn Backend> = match &replay {
+        Some(replay) => Arc::new(replay.clone()),
+        None => backend::from_env()?,
+    };
+    let recorder = match &cli.record {
+        Some(path) => {
+            let recorder = Recorder::create(path, docker, &project.name)?;
+            docker = Arc::new(recorder.clone());
+            Some(recorder)
+        }
+        None => None,
+    };
+    let services = match &replay {
+        Some(replay) => replay.services(),
+        None => docker_compose::services(&project, docker.as_ref()).await?,
+    };
+    let services: Vec<Service> = services
+        .into_iter()
+        .filter(|s| cli.selects(&s.service_name))
+        .collect();
+    // Drops the session directory and writes out the rest of the recording
+    let finish = || -> io::Result<()> {
+        if let Some(dir) = &session {
+            let _ = std::fs::remove_dir_all(dir);
+        }
+        recorder.as_ref().map_or(Ok(()), Recorder::flush)
+    };
+
+    let backlog = cli.backlog();
+    let collect = |s: &Service| -> Result<LogCollector, Box<dyn Error>> {
+        let collector = match &session {
+            Some(dir) => {
+                let store = SegmentedStore::create(
+                    dir.join(&s.service_name),
+                    Retention::unbounded(),
+                    DEFAULT_SEGMENT_BYTES,
+                )?;
+                LogCollector::with_store(docker.clone(), s, Box::new(store))
+            }
+            // An export is written at once, so nothing may be evicted before
+            None if matches!(cli.command, Some(Command::Export(_))) => {
+                let store = RingBuffer::new(Retention::unbounded());
+                LogCollector::with_store(docker.clone(), s, Box::new(store))
+            }
+            None => {
+                let store = RingBuffer::new(cli.retention(s));
+                LogCollector::with_store(docker.clone(), s, Box::new(store))
+            }
+        };
+        let mut collector = collector
+            .with_backlog(backlog)
+            .with_format(cli.format(s))
+            .with_multiline(cli.multiline(s));
+        collector.start();
+        Ok(collector)
+    };
+    let collectors = services
+        .iter()
+        .map(&collect)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    if let Some(Command::Export(export)) = &cli.command {
+        let result = export::run(&cli, export, collectors).await;
+        finish()?;
+        return result;
+    }
+
+    let mut events =
+        Discovery::new(docker.clone(), &project, services.clone()).watch(DISCOVERY_INTERVAL);
+
+    if cli.no_tui {
+        let result =
+            stream::run(&cli, docker.clone(), services, collectors, events, &collect).await;
+        finish()?;
+        return match result? {
+            0 => Ok(()),
+            code => process::exit(code),
+        };
+    }
+
     enable_raw_mode()?;
 
     let mut stdout = io::stdout();
@@ -57,76 +182,38 @@ async fn main() -> Result<(), Box<dyn Error>> {
         }
     });
 
-    let services = docker_compose::services().await?;
-    // println!("main: services: {:?}", services);
-
-    let mut collectors = vec![];
-    services.iter().for_each(|s| {
-        let mut collector = LogCollector::new(&s.service_name, &s.container_name);
-        collector.start();
-        collectors.push(collector);
-        // println!("main: collector: '{}' started", s.service_name);
-    });
-    // let collectors = stream::select_all(collectors);
-
-    /*
-    thread::spawn(|| {
-        while let Some((service_name, total, diff)) = collectors.next().await {
-            println!("main: {}: {} [+{}]", service_name, total, diff);
-        }
-    });
-    */
-
-    let max = (services.len() - 1) as i32;
-    let mut current = 0;
+    let mut app = App::new(services, collectors, cli.theme)
+        .with_filters(cli.filter.clone(), cli.service_filter.clone())
+        .with_ansi(!cli.no_ansi)
+        .with_clock(replay.map(|replay| replay.clock().clone()));
 
     loop {
-        let _ = terminal.draw(|f| {
-            let service = services.get(current as usize).unwrap();
-            let size = f.size();
-            let block = Block::default()
-                .title(service.service_name.clone())
-                .borders(Borders::ALL);
-
-            let collector = collectors.get(current as usize).unwrap();
-            let text: Vec<Spans> = collector
-                .slice()
-                .into_iter()
-                .map(|line| Spans::from(line))
-                .collect();
-            let paragraph = Paragraph::new(text).block(block).wrap(Wrap { trim: true });
-            f.render_widget(paragraph, size);
-        });
-
-        match rx.recv() {
-            Ok(Event::Input(event)) => match event.code {
-                KeyCode::Char('h') => {
-                    current = clamp(current, -1, max);
-                }
-                KeyCode::Char('l') => {
-                    current = clamp(current, 1, max);
-                }
-                KeyCode::Char('q') => {
-                    disable_raw_mode();
-                    execute!(terminal.backend_mut(), LeaveAlternateScreen);
-                    terminal.show_cursor();
-                    break;
+        while let Ok(event) = events.try_recv() {
+            if !cli.selects(event.service_name()) {
+                continue;
+            }
+            match event {
+                ServiceEvent::Added(service) => {
+                    let collector = collect(&service)?;
+                    app.add_service(service, collector);
                 }
-                _ => {}
-            },
-            _ => (),
+                ServiceEvent::Changed(service) => app.update_service(service),
+                ServiceEvent::Removed(name) => app.remove_service(&name),
+            }
+        }
+        app.update();
+        let _ = terminal.draw(|f| ui::draw(f, &mut app));
+
+        if let Ok(Event::Input(event)) = rx.recv() {
+            if !app.on_key(event) {
+                disable_raw_mode()?;
+                execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
+                terminal.show_cursor()?;
+                finish()?;
+                break;
+            }
         }
     }
 
     Ok(())
 }
-
-fn clamp(n: i32, d: i32, max: i32) -> i32 {
-    let mut n = n + d;
-    if n < 0 {
-        n = 0;
-    } else if n > max {
-        n = max;
-    }
-    n
-}
@@ -0,0 +1,200 @@
+use crate::cli::{Cli, Theme};
+use crate::ui::label;
+use crossterm::style::{Color, Stylize};
+use decom_core::{Backend, LogCollector, LogRecord, LogStream, Service, ServiceEvent};
+use std::{
+    borrow::Cow,
+    error::Error,
+    io::{self, IsTerminal, Write},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::sync::mpsc::UnboundedReceiver;
+
+// How long records are still written once every container has stopped, as followers may not
+// have read the last lines yet
+const STOP_GRACE: Duration = Duration::from_secs(1);
+
+/// Writes records of all collectors to stdout as they arrive, with a `service |` prefix.
+///
+/// Returns once stdout is closed, with 0, or once every container of the stack has stopped,
+/// with the first non-zero exit code of them if any. With `--until`, it returns with 0 once
+/// the logs up to then have been written.
+pub async fn run(
+    cli: &Cli,
+    backend: Arc<dyn Backend>,
+    mut services: Vec<Service>,
+    mut collectors: Vec<LogCollector>,
+    mut events: UnboundedReceiver<ServiceEvent>,
+    collect: impl Fn(&Service) -> Result<LogCollector, Box<dyn Error>>,
+) -> Result<i32, Box<dyn Error>> {
+    // Colours only go to a terminal, where escape sequences from containers are kept as well
+    let colored = io::stdout().is_terminal() && cli.theme != Theme::Mono;
+    let mut cursors = vec![0; collectors.len()];
+    let mut stopped: Option<Instant> = None;
+    let mut interval = tokio::time::interval(Duration::from_millis(100));
+    loop {
+        interval.tick().await;
+        while let Ok(event) = events.try_recv() {
+            if !cli.selects(event.service_name()) {
+                continue;
+            }
+            let index = services
+                .iter()
+                .position(|s| s.service_name == event.service_name());
+            match (event, index) {
+                (ServiceEvent::Added(service), None) => {
+                    collectors.push(collect(&service)?);
+                    services.push(service);
+                    cursors.push(0);
+                }
+                (ServiceEvent::Changed(service), Some(i)) => {
+                    collectors[i].update(&service);
+                    services[i] = service;
+                }
+                (ServiceEvent::Removed(_), Some(i)) => {
+                    services.remove(i);
+                    collectors.remove(i);
+                    cursors.remove(i);
+                }
+                _ => {}
+            }
+        }
+
+        // Records of all services since the last tick, in the order they were written
+        let mut records: Vec<(usize, LogRecord)> = vec![];
+        let mut quiet = true;
+        for (i, (collector, cursor)) in collectors.iter().zip(cursors.iter_mut()).enumerate() {
+            for record in collector.since(*cursor) {
+                *cursor = record.seq + 1;
+                quiet = false;
+                if cli.shows(collector.service_name(), &record, collector.format()) {
+                    records.push((i, record));
+                }
+            }
+        }
+        records.sort_by_key(|(_, record)| record.timestamp);
+
+        let width = services
+            .iter()
+            .flat_map(|s| s.replicas.iter().map(move |r| label(s, r.number).len()))
+            .max()
+            .unwrap_or(0);
+        let stdout = io::stdout();
+        let mut out = stdout.lock();
+        for (i, record) in &records {
+            let prefix = format!(
+                "{:width$} |",
+                label(&services[*i], record.replica),
+                width = width
+            );
+            let prefix = if colored {
+                prefix.with(color(*i, cli.theme)).to_string()
+            } else {
+                prefix
+            };
+            let text = if colored && !cli.no_ansi {
+                Cow::Borrowed(record.raw.as_str())
+            } else {
+                record.text()
+            };
+            // Each line of a joined record gets the prefix, to keep the output greppable
+            for line in text.split('\n') {
+                let line = match record.stream {
+                    LogStream::Event if colored => line.bold().to_string(),
+                    _ => line.to_string(),
+                };
+                match writeln!(out, "{} {}", prefix, line) {
+                    Err(e) if e.kind() == io::ErrorKind::BrokenPipe => return Ok(0),
+                    result => result?,
+                }
+            }
+        }
+
+        // Containers aren't followed with `--until`, so it's over once the history is written
+        if cli.until.is_none() && services.iter().any(is_up) {
+            stopped = None;
+            continue;
+        }
+        let since = *stopped.get_or_insert_with(Instant::now);
+        if since.elapsed() >= STOP_GRACE && quiet {
+            return match cli.until {
+                Some(_) => Ok(0),
+                None => Ok(exit_code(backend.as_ref(), &services).await),
+            };
+        }
+    }
+}
+
+// Whether a container of the service is running or about to
+fn is_up(service: &Service) -> bool {
+    service
+        .replicas
+        .iter()
+        .any(|r| ["running", "restarting", "created", "paused"].contains(&r.state.as_str()))
+}
+
+// First non-zero exit code of the replicas, in the order of services
+async fn exit_code(backend: &dyn Backend, services: &[Service]) -> i32 {
+    for replica in services.iter().flat_map(|s| &s.replicas) {
+        if let Ok(details) = backend.inspect(&replica.container_id).await {
+            if details.state.exit_code != 0 {
+                return details.state.exit_code as i32;
+            }
+        }
+    }
+    0
+}
+
+fn color(i: usize, theme: Theme) -> Color {
+    // The same as labels in the timeline
+    const DARK: [Color; 6] = [
+        Color::Cyan,
+        Color::Yellow,
+        Color::Green,
+        Color::Magenta,
+        Color::Blue,
+        Color::Red,
+    ];
+    const LIGHT: [Color; 6] = [
+        Color::Blue,
+        Color::Magenta,
+        Color::Green,
+        Color::Red,
+        Color::Cyan,
+        Color::DarkGrey,
+    ];
+    match theme {
+        Theme::Light => LIGHT[i % LIGHT.len()],
+        _ => DARK[i % DARK.len()],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use decom_core::{Format, Multiline, Replica, Retention};
+
+    #[test]
+    fn test_is_up() {
+        let service = |states: &[&str]| Service {
+            service_name: "web".into(),
+            replicas: states
+                .iter()
+                .enumerate()
+                .map(|(i, state)| Replica {
+                    number: i as u32 + 1,
+                    container_name: format!("app_web_{}", i + 1),
+                    container_id: i.to_string(),
+                    state: state.to_string(),
+                })
+                .collect(),
+            format: Format::Auto,
+            multiline: Multiline::Auto,
+            retention: Retention::default(),
+        };
+        assert!(is_up(&service(&["exited", "running"])));
+        assert!(is_up(&service(&["restarting"])));
+        assert!(!is_up(&service(&["exited", "dead"])));
+    }
+}
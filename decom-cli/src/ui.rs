@@ -0,0 +1,548 @@
+use crate::app::{App, Mode, PromptKind};
+use crate::cli::Theme;
+use chrono::Local;
+use decom_core::{ansi, Format, Level, LogRecord, LogStream, Query, Service};
+use tui::{
+    backend::Backend,
+    layout::{Constraint, Direction, Layout, Rect},
+    style::{Color, Modifier, Style},
+    text::{Span, Spans},
+    widgets::{Block, Borders, Paragraph, Tabs, Wrap},
+    Frame,
+};
+
+pub fn draw<B: Backend>(f: &mut Frame<B>, app: &mut App) {
+    let mut area = f.size();
+    if app.prompt.is_some() || app.message.is_some() {
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Min(0), Constraint::Length(1)].as_ref())
+            .split(area);
+        draw_prompt(f, app, chunks[1]);
+        area = chunks[0];
+    }
+    app.page = match app.mode {
+        Mode::Service => draw_service(f, app, area),
+        Mode::Timeline => draw_timeline(f, app, area),
+    };
+}
+
+fn draw_prompt<B: Backend>(f: &mut Frame<B>, app: &App, area: Rect) {
+    let prompt = match (&app.prompt, &app.message) {
+        (Some(prompt), _) => prompt,
+        (None, Some(message)) => {
+            f.render_widget(Paragraph::new(message.as_str()), area);
+            return;
+        }
+        (None, None) => return,
+    };
+    let label = match prompt.kind {
+        PromptKind::Search { backward: false } => "/",
+        PromptKind::Search { backward: true } => "?",
+        PromptKind::Filter => "filter: ",
+        PromptKind::GlobalFilter => "global filter: ",
+        PromptKind::Export => "export to: ",
+    };
+    let mut spans = vec![Span::raw(format!("{}{}", label, prompt.input))];
+    let mut flags = vec![];
+    if let PromptKind::Search { .. } = prompt.kind {
+        if app.search_options.regex {
+            flags.push("regex");
+        }
+        if app.search_options.ignore_case {
+            flags.push("ignore case");
+        }
+    }
+    if !flags.is_empty() {
+        spans.push(Span::styled(
+            format!("  [{}]", flags.join(", ")),
+            Style::default().add_modifier(Modifier::DIM),
+        ));
+    }
+    if let Some(error) = &prompt.error {
+        spans.push(Span::styled(
+            format!("  {}", error),
+            Style::default().fg(Color::Red),
+        ));
+    }
+    f.render_widget(Paragraph::new(Spans::from(spans)), area);
+}
+
+/// Draws the current service, returns the height of the log pane.
+fn draw_service<B: Backend>(f: &mut Frame<B>, app: &App, size: Rect) -> usize {
+    if app.services.is_empty() {
+        let block = Block::default()
+            .title("waiting for containers")
+            .borders(Borders::ALL);
+        f.render_widget(block, size);
+        return 0;
+    }
+
+    let service = &app.services[app.current];
+    let collector = &app.collectors[app.current];
+    let view = app.views[app.current];
+
+    let mut title = title(&service.service_name, app.stream);
+    if !collector.is_complete() {
+        title.push_str(" (m: older logs)");
+    }
+    if !app.is_following() {
+        title.push_str(" (paused, G: follow)");
+    }
+    filters(&mut title, app);
+    replay(&mut title, app);
+    search(&mut title, app);
+    let replicas = collector.replicas();
+    let (area, block) = if replicas.len() > 1 {
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Length(3), Constraint::Min(0)].as_ref())
+            .split(size);
+        let titles: Vec<Spans> = std::iter::once("all".to_string())
+            .chain(replicas.iter().map(|r| format!("#{}", r.number)))
+            .map(Spans::from)
+            .collect();
+        let tabs = Tabs::new(titles)
+            .block(Block::default().title(title).borders(Borders::ALL))
+            .select(view)
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+        f.render_widget(tabs, chunks[0]);
+        (chunks[1], Block::default().borders(Borders::ALL))
+    } else {
+        (size, Block::default().title(title).borders(Borders::ALL))
+    };
+
+    let tagged = replicas.len() > 1 && app.replica().is_none();
+    let height = area.height.saturating_sub(2) as usize;
+    let text: Vec<Spans> = app
+        .lines(height)
+        .into_iter()
+        .flat_map(|(_, record)| {
+            let mut spans = vec![];
+            if app.show_time {
+                spans.push(time(&record, app.theme));
+            }
+            if tagged {
+                spans.push(Span::styled(
+                    format!("{} | ", record.replica),
+                    label_style(record.replica as usize, app.theme),
+                ));
+            }
+            rows(spans, &record, app.collectors[app.current].format(), app)
+        })
+        .collect();
+    // Keeps the newest lines in sight when long or expanded ones take several rows
+    let scroll = wrapped(&text, area.width.saturating_sub(2)).saturating_sub(height);
+    let paragraph = Paragraph::new(text)
+        .block(block)
+        .wrap(Wrap { trim: false })
+        .scroll((scroll as u16, 0));
+    f.render_widget(paragraph, area);
+    height
+}
+
+/// Draws the timeline of all services, returns the height of the log pane.
+fn draw_timeline<B: Backend>(f: &mut Frame<B>, app: &App, size: Rect) -> usize {
+    let order = if app.newest_first {
+        "newest first"
+    } else {
+        "oldest first"
+    };
+    let mut title = title(&format!("timeline ({})", order), app.stream);
+    if !app.collectors.iter().all(|c| c.is_complete()) {
+        title.push_str(" (m: older logs)");
+    }
+    if !app.is_following() {
+        title.push_str(" (paused, G: follow)");
+    }
+    filters(&mut title, app);
+    replay(&mut title, app);
+    search(&mut title, app);
+    let block = Block::default().title(title).borders(Borders::ALL);
+
+    let width = app
+        .services
+        .iter()
+        .flat_map(|s| s.replicas.iter().map(move |r| label(s, r.number).len()))
+        .max()
+        .unwrap_or(0);
+    let height = size.height.saturating_sub(2) as usize;
+
+    let entries = app.timeline.entries();
+    let mut records: Vec<(usize, LogRecord)> = app
+        .lines(height)
+        .into_iter()
+        .map(|(index, record)| (entries[index].source, record))
+        .collect();
+    if app.newest_first {
+        records.reverse();
+    }
+
+    let text: Vec<Spans> = records
+        .into_iter()
+        .flat_map(|(source, record)| {
+            let mut spans = vec![];
+            if app.show_time {
+                spans.push(time(&record, app.theme));
+            }
+            let name = label(&app.services[source], record.replica);
+            spans.push(Span::styled(
+                format!("{:width$} | ", name, width = width),
+                label_style(source + 1, app.theme),
+            ));
+            rows(spans, &record, app.collectors[source].format(), app)
+        })
+        .collect();
+    let scroll = if app.newest_first {
+        0
+    } else {
+        wrapped(&text, size.width.saturating_sub(2)).saturating_sub(height)
+    };
+    let paragraph = Paragraph::new(text)
+        .block(block)
+        .wrap(Wrap { trim: false })
+        .scroll((scroll as u16, 0));
+    f.render_widget(paragraph, size);
+    height
+}
+
+// Appends filters applied to the pane to its title
+fn filters(title: &mut String, app: &App) {
+    if let Some(filter) = app.pane_filter() {
+        title.push_str(&format!(" (filter: {})", filter));
+    }
+    if let Some(filter) = &app.global_filter {
+        title.push_str(&format!(" (global filter: {})", filter));
+    }
+}
+
+// Appends the pattern searched and how many matches were found to the title
+fn search(title: &mut String, app: &App) {
+    if let Some(search) = &app.search {
+        title.push_str(&format!(
+            " (/{}: {} matches{})",
+            search.query().pattern(),
+            search.len(),
+            if search.is_done() { "" } else { "..." }
+        ));
+    }
+}
+
+// Appends where a replay is and how fast it goes to the title
+fn replay(title: &mut String, app: &App) {
+    let clock = match &app.clock {
+        Some(clock) => clock,
+        None => return,
+    };
+    let now = clock.now().with_timezone(&Local).format("%H:%M:%S");
+    if clock.is_over() {
+        title.push_str(&format!(" (replay ended at {})", now));
+    } else if clock.is_paused() {
+        title.push_str(&format!(" (replay at {}, stopped, space: play)", now));
+    } else {
+        title.push_str(&format!(" (replay at {}, {}x)", now, clock.speed()));
+    }
+}
+
+/// Name of a replica, with its number if the service has several.
+pub fn label(service: &Service, replica: u32) -> String {
+    if service.replicas.len() > 1 {
+        format!("{}.{}", service.service_name, replica)
+    } else {
+        service.service_name.clone()
+    }
+}
+
+fn title(name: &str, stream: Option<LogStream>) -> String {
+    match stream {
+        Some(LogStream::Stdout) => format!("{} [stdout]", name),
+        Some(LogStream::Stderr) => format!("{} [stderr]", name),
+        Some(LogStream::Event) => format!("{} [events]", name),
+        None => name.to_string(),
+    }
+}
+
+fn time(record: &LogRecord, theme: Theme) -> Span<'static> {
+    let style = match theme {
+        Theme::Dark => Style::default().fg(Color::DarkGray),
+        Theme::Light => Style::default().fg(Color::Gray),
+        Theme::Mono => Style::default(),
+    };
+    Span::styled(
+        format!(
+            "{} ",
+            record
+                .timestamp
+                .with_timezone(&Local)
+                .format("%H:%M:%S%.3f")
+        ),
+        style,
+    )
+}
+
+// Rows showing a record after the prefix, more than one for an expanded structured line or
+// an unfolded record of several lines
+fn rows(
+    prefix: Vec<Span<'static>>,
+    record: &LogRecord,
+    format: Format,
+    app: &App,
+) -> Vec<Spans<'static>> {
+    let style = match (record.stream, app.theme) {
+        (LogStream::Stdout, _) => Style::default(),
+        (LogStream::Stderr, Theme::Dark) => Style::default().fg(Color::LightRed),
+        (LogStream::Stderr, Theme::Light) => Style::default().fg(Color::Red),
+        (LogStream::Stderr, Theme::Mono) => Style::default().add_modifier(Modifier::BOLD),
+        (LogStream::Event, Theme::Mono) => Style::default().add_modifier(Modifier::REVERSED),
+        (LogStream::Event, _) => Style::default()
+            .fg(Color::Yellow)
+            .add_modifier(Modifier::BOLD),
+    };
+    let structured = match record.stream {
+        LogStream::Event => None,
+        _ => record.parse_as(format),
+    };
+    // Lines of the record as it's written, colours carrying over to later lines
+    let mut lines = styled_lines(&record.raw, style, app).into_iter();
+    let head = lines.next().unwrap_or_default();
+    let (segments, mut more) = match structured {
+        // Text lines stay as they are, in the colour of a notable level
+        Some(structured) if structured.format == Format::Plain => match structured.level {
+            Some(level) if level >= Level::Warn => {
+                let style = level_style(level, app.theme);
+                let text = head.into_iter().map(|(text, _)| text).collect();
+                (vec![(text, style)], vec![])
+            }
+            _ => (head, vec![]),
+        },
+        Some(structured) => {
+            let dim = Style::default().add_modifier(Modifier::DIM);
+            let mut segments = vec![];
+            if let Some(level) = structured.level {
+                let name = format!("{:?}", level).to_uppercase();
+                segments.push((format!("{:5} ", name), level_style(level, app.theme)));
+            }
+            segments.push((structured.msg.clone().unwrap_or_default(), style));
+            match structured.pretty {
+                Some(pretty) if app.expanded => {
+                    let more = pretty
+                        .lines()
+                        .map(|line| vec![(format!("  {}", line), style)])
+                        .collect();
+                    (segments, more)
+                }
+                _ => {
+                    for (key, value) in structured.fields {
+                        segments.push((format!(" {}=", key), dim));
+                        segments.push((quote(value), style));
+                    }
+                    (segments, vec![])
+                }
+            }
+        }
+        None => (head, vec![]),
+    };
+
+    let mut first = prefix;
+    first.extend(highlight(segments, app.query(), app.theme));
+    // Lines joined to the record, such as a stack trace
+    let joined = lines.len();
+    if app.folded && joined > 0 {
+        first.push(Span::styled(
+            format!(" (+{} lines)", joined),
+            Style::default().add_modifier(Modifier::DIM),
+        ));
+    } else {
+        more.extend(lines);
+    }
+    std::iter::once(Spans::from(first))
+        .chain(
+            more.into_iter()
+                .map(|line| Spans::from(highlight(line, app.query(), app.theme))),
+        )
+        .collect()
+}
+
+// Segments of each line of the text, in the colours its SGR sequences set over the style
+// unless they're turned off
+fn styled_lines(text: &str, style: Style, app: &App) -> Vec<Vec<(String, Style)>> {
+    let segments = if app.ansi {
+        ansi::parse(text)
+            .into_iter()
+            .map(|(text, ansi)| (text, ansi_style(style, ansi, app.theme)))
+            .collect()
+    } else {
+        vec![(ansi::strip(text).into_owned(), style)]
+    };
+    let mut lines = vec![vec![]];
+    for (text, style) in segments {
+        for (i, part) in text.split('\n').enumerate() {
+            if i > 0 {
+                lines.push(vec![]);
+            }
+            if !part.is_empty() {
+                lines.last_mut().unwrap().push((part.to_string(), style));
+            }
+        }
+    }
+    lines
+}
+
+fn ansi_style(base: Style, ansi: ansi::Style, theme: Theme) -> Style {
+    let mut style = base;
+    if theme != Theme::Mono {
+        if let Some(color) = ansi.fg {
+            style = style.fg(ansi_color(color));
+        }
+        if let Some(color) = ansi.bg {
+            style = style.bg(ansi_color(color));
+        }
+    }
+    let modifiers = [
+        (ansi.bold, Modifier::BOLD),
+        (ansi.dim, Modifier::DIM),
+        (ansi.italic, Modifier::ITALIC),
+        (ansi.underline, Modifier::UNDERLINED),
+        (ansi.reversed, Modifier::REVERSED),
+    ];
+    for (on, modifier) in modifiers {
+        if on {
+            style = style.add_modifier(modifier);
+        }
+    }
+    style
+}
+
+fn ansi_color(color: ansi::Color) -> Color {
+    const STANDARD: [Color; 16] = [
+        Color::Black,
+        Color::Red,
+        Color::Green,
+        Color::Yellow,
+        Color::Blue,
+        Color::Magenta,
+        Color::Cyan,
+        Color::Gray,
+        Color::DarkGray,
+        Color::LightRed,
+        Color::LightGreen,
+        Color::LightYellow,
+        Color::LightBlue,
+        Color::LightMagenta,
+        Color::LightCyan,
+        Color::White,
+    ];
+    match color {
+        ansi::Color::Indexed(n) if (n as usize) < STANDARD.len() => STANDARD[n as usize],
+        ansi::Color::Indexed(n) => Color::Indexed(n),
+        ansi::Color::Rgb(r, g, b) => Color::Rgb(r, g, b),
+    }
+}
+
+// Quotes a field value which wouldn't read as one
+fn quote(value: String) -> String {
+    if value.is_empty() || value.contains(|c: char| c.is_whitespace() || c == '"') {
+        format!("{:?}", value)
+    } else {
+        value
+    }
+}
+
+/// Spans of text segments, with matches of the query highlighted.
+fn highlight(
+    segments: Vec<(String, Style)>,
+    query: Option<&Query>,
+    theme: Theme,
+) -> Vec<Span<'static>> {
+    let query = match query {
+        Some(query) => query,
+        None => {
+            return segments
+                .into_iter()
+                .map(|(text, style)| Span::styled(text, style))
+                .collect()
+        }
+    };
+    let whole: String = segments.iter().map(|(text, _)| text.as_str()).collect();
+    let ranges: Vec<_> = query.find_iter(&whole).collect();
+
+    let mut spans = vec![];
+    let mut offset = 0;
+    for (text, style) in segments {
+        let highlighted = match theme {
+            Theme::Mono => style.add_modifier(Modifier::REVERSED),
+            _ => style.fg(Color::Black).bg(Color::Yellow),
+        };
+        let end = offset + text.len();
+        let mut at = offset;
+        for range in ranges.iter().filter(|r| r.start < end && r.end > offset) {
+            let start = range.start.max(offset);
+            if at < start {
+                spans.push(Span::styled(
+                    text[at - offset..start - offset].to_string(),
+                    style,
+                ));
+            }
+            let stop = range.end.min(end);
+            spans.push(Span::styled(
+                text[start - offset..stop - offset].to_string(),
+                highlighted,
+            ));
+            at = stop;
+        }
+        if at < end {
+            spans.push(Span::styled(text[at - offset..].to_string(), style));
+        }
+        offset = end;
+    }
+    spans
+}
+
+fn level_style(level: Level, theme: Theme) -> Style {
+    let color = match level {
+        Level::Trace => Color::DarkGray,
+        Level::Debug => Color::Blue,
+        Level::Info => Color::Green,
+        Level::Warn => Color::Yellow,
+        Level::Error | Level::Fatal => Color::Red,
+    };
+    match theme {
+        Theme::Mono if level >= Level::Warn => Style::default().add_modifier(Modifier::BOLD),
+        Theme::Mono => Style::default(),
+        _ => Style::default().fg(color),
+    }
+}
+
+// Rows a paragraph takes wrapped to the width
+fn wrapped(rows: &[Spans], width: u16) -> usize {
+    let width = width.max(1) as usize;
+    rows.iter()
+        .map(|row| row.width().div_ceil(width).max(1))
+        .sum()
+}
+
+/// Style of the label of the n-th (1-based) replica or service.
+fn label_style(n: usize, theme: Theme) -> Style {
+    const DARK: [Color; 6] = [
+        Color::Cyan,
+        Color::Yellow,
+        Color::Green,
+        Color::Magenta,
+        Color::Blue,
+        Color::Red,
+    ];
+    const LIGHT: [Color; 6] = [
+        Color::Blue,
+        Color::Magenta,
+        Color::Green,
+        Color::Red,
+        Color::Cyan,
+        Color::DarkGray,
+    ];
+    let i = n.saturating_sub(1) % DARK.len();
+    match theme {
+        Theme::Dark => Style::default().fg(DARK[i]),
+        Theme::Light => Style::default().fg(LIGHT[i]),
+        Theme::Mono => Style::default().add_modifier(Modifier::BOLD),
+    }
+}
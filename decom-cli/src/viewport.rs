@@ -0,0 +1,279 @@
+use decom_core::{timeline::Entry, Format, LogCollector, LogRecord};
+use std::collections::HashMap;
+
+// Entries looked up at once while scanning for lines to show
+const CHUNK: usize = 256;
+
+/// Scroll position of a log pane over timeline entries.
+///
+/// The pane follows the newest lines until it's scrolled up. Then it stays at the same
+/// lines, even while new ones arrive, until it's scrolled back to the bottom.
+#[derive(Debug, Default, Clone)]
+pub struct Viewport {
+    // Entry shown at the bottom when not following
+    anchor: Option<Entry>,
+}
+
+/// A line of a pane: the position of its entry in the timeline and its record.
+pub type Line = (usize, LogRecord);
+
+impl Viewport {
+    pub fn is_following(&self) -> bool {
+        self.anchor.is_none()
+    }
+
+    pub fn follow(&mut self) {
+        self.anchor = None;
+    }
+
+    /// Lines to show in a pane of the given height, oldest first.
+    pub fn lines<F>(&self, entries: &[Entry], fetch: F, height: usize) -> Vec<Line>
+    where
+        F: FnMut(&[Entry]) -> Vec<Option<LogRecord>>,
+    {
+        let mut lines = scan_back(entries, self.position(entries), height, fetch);
+        lines.reverse();
+        lines
+    }
+
+    /// Scrolls up by `n` lines. Returns `false` when the top was reached before.
+    pub fn up<F>(&mut self, entries: &[Entry], fetch: F, height: usize, n: usize) -> bool
+    where
+        F: FnMut(&[Entry]) -> Vec<Option<LogRecord>>,
+    {
+        // Before the pane is first drawn
+        if height == 0 {
+            return true;
+        }
+        let lines = scan_back(entries, self.position(entries), n + height, fetch);
+        // Lines stay filling the pane, so the bottom can't move above the first page
+        let k = n.min(lines.len().saturating_sub(height));
+        if k > 0 {
+            self.anchor = Some(entries[lines[k].0]);
+        }
+        lines.len() >= n + height
+    }
+
+    /// Scrolls down by `n` lines, and follows again when there are no more.
+    pub fn down<F>(&mut self, entries: &[Entry], fetch: F, n: usize)
+    where
+        F: FnMut(&[Entry]) -> Vec<Option<LogRecord>>,
+    {
+        if self.is_following() || n == 0 {
+            return;
+        }
+        let lines = scan_forward(entries, self.position(entries), n + 1, fetch);
+        if lines.len() <= n {
+            self.follow();
+        } else {
+            self.anchor = Some(entries[lines[n - 1].0]);
+        }
+    }
+
+    /// Scrolls to the oldest lines.
+    pub fn top<F>(&mut self, entries: &[Entry], fetch: F, height: usize)
+    where
+        F: FnMut(&[Entry]) -> Vec<Option<LogRecord>>,
+    {
+        if height == 0 {
+            return;
+        }
+        let lines = scan_forward(entries, 0, height + 1, fetch);
+        if lines.len() > height {
+            self.anchor = Some(entries[lines[height - 1].0]);
+        } else {
+            self.follow();
+        }
+    }
+
+    /// Scrolls so that the entry is at the bottom.
+    pub fn scroll_to(&mut self, entry: Entry) {
+        self.anchor = Some(entry);
+    }
+
+    /// Index of the timeline entry just below the bottom of the pane.
+    pub fn position(&self, entries: &[Entry]) -> usize {
+        match self.anchor {
+            Some(anchor) => entries.partition_point(|entry| *entry <= anchor),
+            None => entries.len(),
+        }
+    }
+}
+
+/// Looks up records of entries with a single store access per collector. Records which are
+/// evicted or rejected by `filter`, which is given the format of their collector, are `None`.
+pub fn fetch<'a>(
+    collectors: &'a [LogCollector],
+    filter: impl Fn(&LogRecord, Format) -> bool + 'a,
+) -> impl FnMut(&[Entry]) -> Vec<Option<LogRecord>> + 'a {
+    move |entries| {
+        let mut ranges: HashMap<usize, (u64, u64)> = HashMap::new();
+        for entry in entries {
+            let range = ranges.entry(entry.source).or_insert((entry.seq, entry.seq));
+            range.0 = range.0.min(entry.seq);
+            range.1 = range.1.max(entry.seq);
+        }
+        let mut records: HashMap<(usize, u64), LogRecord> = HashMap::new();
+        for (source, (first, last)) in ranges {
+            if let Some(collector) = collectors.get(source) {
+                for record in collector.slice(first..last + 1) {
+                    records.insert((source, record.seq), record);
+                }
+            }
+        }
+        entries
+            .iter()
+            .map(|entry| {
+                records
+                    .remove(&(entry.source, entry.seq))
+                    .filter(|record| filter(record, collectors[entry.source].format()))
+            })
+            .collect()
+    }
+}
+
+// Up to `count` lines before the position, newest first
+fn scan_back<F>(entries: &[Entry], from: usize, count: usize, mut fetch: F) -> Vec<Line>
+where
+    F: FnMut(&[Entry]) -> Vec<Option<LogRecord>>,
+{
+    let mut lines = vec![];
+    let mut end = from.min(entries.len());
+    while end > 0 && lines.len() < count {
+        let start = end.saturating_sub(CHUNK);
+        let records = fetch(&entries[start..end]);
+        for (i, record) in records.into_iter().enumerate().rev() {
+            if let Some(record) = record {
+                lines.push((start + i, record));
+            }
+        }
+        end = start;
+    }
+    lines.truncate(count);
+    lines
+}
+
+// Up to `count` lines at or after the position, oldest first
+fn scan_forward<F>(entries: &[Entry], from: usize, count: usize, mut fetch: F) -> Vec<Line>
+where
+    F: FnMut(&[Entry]) -> Vec<Option<LogRecord>>,
+{
+    let mut lines = vec![];
+    let mut start = from;
+    while start < entries.len() && lines.len() < count {
+        let end = (start + CHUNK).min(entries.len());
+        let records = fetch(&entries[start..end]);
+        for (i, record) in records.into_iter().enumerate() {
+            if let Some(record) = record {
+                lines.push((start + i, record));
+            }
+        }
+        start = end;
+    }
+    lines.truncate(count);
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+    use decom_core::LogStream;
+    use std::sync::Arc;
+
+    fn entries(n: u64) -> Vec<Entry> {
+        (0..n)
+            .map(|seq| Entry {
+                timestamp: Utc.timestamp_opt(seq as i64, 0).unwrap(),
+                source: 0,
+                seq,
+            })
+            .collect()
+    }
+
+    // Records of odd sequence numbers are filtered out
+    fn even(entries: &[Entry]) -> Vec<Option<LogRecord>> {
+        entries
+            .iter()
+            .map(|entry| {
+                Some(LogRecord {
+                    seq: entry.seq,
+                    timestamp: entry.timestamp,
+                    stream: LogStream::Stdout,
+                    container: Arc::from("app_web_1"),
+                    replica: 1,
+                    raw: entry.seq.to_string(),
+                })
+                .filter(|_| entry.seq % 2 == 0)
+            })
+            .collect()
+    }
+
+    fn raws(lines: Vec<Line>) -> Vec<String> {
+        lines.into_iter().map(|(_, record)| record.raw).collect()
+    }
+
+    #[test]
+    fn test_scroll() {
+        let entries = entries(1000);
+        let mut viewport = Viewport::default();
+        assert_eq!(
+            raws(viewport.lines(&entries, even, 3)),
+            ["994", "996", "998"]
+        );
+
+        assert!(viewport.up(&entries, even, 3, 2));
+        assert!(!viewport.is_following());
+        assert_eq!(
+            raws(viewport.lines(&entries, even, 3)),
+            ["990", "992", "994"]
+        );
+
+        // New lines don't move the pane while it's scrolled up
+        let entries = self::entries(1004);
+        assert_eq!(
+            raws(viewport.lines(&entries, even, 3)),
+            ["990", "992", "994"]
+        );
+
+        viewport.down(&entries, even, 3);
+        assert_eq!(
+            raws(viewport.lines(&entries, even, 3)),
+            ["996", "998", "1000"]
+        );
+        assert!(!viewport.is_following());
+
+        // Reaching the newest line follows again
+        viewport.down(&entries, even, 1);
+        assert!(viewport.is_following());
+        assert_eq!(
+            raws(viewport.lines(&entries, even, 3)),
+            ["998", "1000", "1002"]
+        );
+    }
+
+    #[test]
+    fn test_top() {
+        let entries = entries(1000);
+        let mut viewport = Viewport::default();
+        viewport.top(&entries, even, 3);
+        assert_eq!(raws(viewport.lines(&entries, even, 3)), ["0", "2", "4"]);
+
+        // Nothing moves without a pane to show lines in
+        let mut hidden = Viewport::default();
+        hidden.top(&entries, even, 0);
+        assert!(hidden.up(&entries, even, 0, 1));
+        assert!(hidden.is_following());
+
+        // Scrolling up at the top tells it, and keeps the pane filled
+        assert!(!viewport.up(&entries, even, 3, 1));
+        assert_eq!(raws(viewport.lines(&entries, even, 3)), ["0", "2", "4"]);
+
+        // Lines which fit in the pane are followed
+        let entries = self::entries(4);
+        viewport.top(&entries, even, 3);
+        assert!(viewport.is_following());
+        assert!(!viewport.up(&entries, even, 3, 1));
+        assert!(viewport.is_following());
+    }
+}
@@ -0,0 +1,187 @@
+use std::borrow::Cow;
+
+/// Colour set by an SGR sequence, one of the 256 of the palette or an RGB one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Color {
+    /// 0-7 are the standard colours, 8-15 their bright variants.
+    Indexed(u8),
+    Rgb(u8, u8, u8),
+}
+
+/// Text attributes set by SGR sequences.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Style {
+    pub fg: Option<Color>,
+    pub bg: Option<Color>,
+    pub bold: bool,
+    pub dim: bool,
+    pub italic: bool,
+    pub underline: bool,
+    pub reversed: bool,
+}
+
+impl Style {
+    // The style after an SGR sequence with the parameters, e.g. `1;31`
+    fn apply(mut self, params: &str) -> Style {
+        // Subparameters such as `38:5:208` are taken like plain ones
+        let mut params = params
+            .split([';', ':'])
+            .map(|p| p.parse::<u16>().unwrap_or(0));
+        // `ESC[m` is a reset
+        let mut next = Some(params.next().unwrap_or(0));
+        while let Some(code) = next {
+            match code {
+                0 => self = Style::default(),
+                1 => self.bold = true,
+                2 => self.dim = true,
+                3 => self.italic = true,
+                4 => self.underline = true,
+                7 => self.reversed = true,
+                22 => {
+                    self.bold = false;
+                    self.dim = false;
+                }
+                23 => self.italic = false,
+                24 => self.underline = false,
+                27 => self.reversed = false,
+                30..=37 => self.fg = Some(Color::Indexed((code - 30) as u8)),
+                38 => self.fg = extended(&mut params),
+                39 => self.fg = None,
+                40..=47 => self.bg = Some(Color::Indexed((code - 40) as u8)),
+                48 => self.bg = extended(&mut params),
+                49 => self.bg = None,
+                90..=97 => self.fg = Some(Color::Indexed((code - 90 + 8) as u8)),
+                100..=107 => self.bg = Some(Color::Indexed((code - 100 + 8) as u8)),
+                _ => {}
+            }
+            next = params.next();
+        }
+        self
+    }
+}
+
+// Colour of `38;5;N` or `38;2;R;G;B`, after the 38 or 48
+fn extended(params: &mut impl Iterator<Item = u16>) -> Option<Color> {
+    let mut byte = || params.next().map(|n| n.min(255) as u8);
+    match byte()? {
+        5 => Some(Color::Indexed(byte()?)),
+        2 => Some(Color::Rgb(byte()?, byte()?, byte()?)),
+        _ => None,
+    }
+}
+
+/// Splits text into segments of the style its SGR sequences set.
+///
+/// Other escape sequences, such as cursor movements and window titles, and control characters
+/// other than tabs and newlines are dropped.
+pub fn parse(text: &str) -> Vec<(String, Style)> {
+    let mut segments = vec![];
+    let mut style = Style::default();
+    let mut current = String::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\x1b' => match chars.next() {
+                // CSI, parameters and intermediates up to a final byte
+                Some('[') => {
+                    let mut params = String::new();
+                    let mut end = None;
+                    for c in chars.by_ref() {
+                        if ('@'..='~').contains(&c) {
+                            end = Some(c);
+                            break;
+                        }
+                        params.push(c);
+                    }
+                    if end == Some('m') {
+                        let next = style.apply(&params);
+                        if next != style && !current.is_empty() {
+                            segments.push((std::mem::take(&mut current), style));
+                        }
+                        style = next;
+                    }
+                }
+                // OSC, up to BEL or ST
+                Some(']') => {
+                    while let Some(c) = chars.next() {
+                        if c == '\x07' {
+                            break;
+                        }
+                        if c == '\x1b' {
+                            chars.next();
+                            break;
+                        }
+                    }
+                }
+                // Such as `ESC ( B`, with an intermediate byte
+                Some(' '..='/') => {
+                    chars.next();
+                }
+                _ => {}
+            },
+            '\t' | '\n' => current.push(c),
+            c if c.is_control() => {}
+            c => current.push(c),
+        }
+    }
+    if !current.is_empty() {
+        segments.push((current, style));
+    }
+    segments
+}
+
+/// The text without escape sequences and control characters.
+pub fn strip(text: &str) -> Cow<'_, str> {
+    if text
+        .chars()
+        .any(|c| c.is_control() && c != '\t' && c != '\n')
+    {
+        Cow::Owned(parse(text).into_iter().map(|(text, _)| text).collect())
+    } else {
+        Cow::Borrowed(text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let red = Style {
+            fg: Some(Color::Indexed(1)),
+            bold: true,
+            ..Style::default()
+        };
+        assert_eq!(
+            parse("\x1b[1;31mERROR\x1b[0m failed"),
+            [
+                ("ERROR".to_string(), red),
+                (" failed".to_string(), Style::default())
+            ]
+        );
+
+        let segments = parse("\x1b[38;5;208mA\x1b[48:2:1:2:3mB\x1b[39;49;92mC\x1b[mD");
+        let styles: Vec<Style> = segments.iter().map(|(_, style)| *style).collect();
+        assert_eq!(styles[0].fg, Some(Color::Indexed(208)));
+        assert_eq!(styles[1].bg, Some(Color::Rgb(1, 2, 3)));
+        assert_eq!(styles[2].fg, Some(Color::Indexed(10)));
+        assert_eq!(styles[2].bg, None);
+        assert_eq!(styles[3], Style::default());
+
+        // Escape sequences which aren't SGR are dropped
+        assert_eq!(
+            parse("\x1b]0;title\x07\x1b[2K\rdone\x1b(B\tok"),
+            [("done\tok".to_string(), Style::default())]
+        );
+    }
+
+    #[test]
+    fn test_strip() {
+        assert!(matches!(strip("plain\ttext"), Cow::Borrowed(_)));
+        assert_eq!(
+            strip("\x1b[32mINFO\x1b[0m started\n  \x1b[2mat main\x1b[0m"),
+            "INFO started\n  at main"
+        );
+    }
+}
@@ -0,0 +1,106 @@
+use crate::docker::{Container, ContainerDetails, DockerCli};
+use crate::docker_api::{DockerApi, Host};
+use crate::error::Error;
+use crate::record::split_timestamp;
+use chrono::{DateTime, Utc};
+use futures::{future::BoxFuture, stream::BoxStream};
+use std::{env, sync::Arc};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LogStream {
+    Stdout,
+    Stderr,
+    /// Lines inserted by decom itself, e.g. a marker where a container restarted.
+    Event,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogLine {
+    pub stream: LogStream,
+    pub timestamp: Option<DateTime<Utc>>,
+    pub text: String,
+}
+
+impl LogLine {
+    pub fn new(stream: LogStream, text: String) -> Self {
+        LogLine {
+            stream,
+            timestamp: None,
+            text,
+        }
+    }
+
+    pub(crate) fn with_timestamp(self) -> Self {
+        match split_timestamp(&self.text) {
+            (Some(timestamp), text) => LogLine {
+                stream: self.stream,
+                timestamp: Some(timestamp),
+                text: text.to_string(),
+            },
+            (None, _) => self,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ContainerFilter {
+    pub labels: Vec<(String, String)>,
+}
+
+impl ContainerFilter {
+    pub fn label(mut self, key: &str, value: &str) -> Self {
+        self.labels.push((key.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn matches(&self, container: &Container) -> bool {
+        self.labels
+            .iter()
+            .all(|(key, value)| container.label(key) == Some(value.as_str()))
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct LogsOptions {
+    pub follow: bool,
+    /// Ask Docker for the time of each line, which is then moved into `LogLine::timestamp`.
+    pub timestamps: bool,
+    /// Only lines written at or after this time.
+    pub since: Option<DateTime<Utc>>,
+    /// Only lines written before this time.
+    pub until: Option<DateTime<Utc>>,
+    /// Only this many of the latest lines already written.
+    pub tail: Option<usize>,
+}
+
+/// Source of container information and logs, either the `docker` command or the Engine API.
+pub trait Backend: Send + Sync {
+    /// Lists containers matching the filter, including stopped ones.
+    fn containers(&self, filter: &ContainerFilter) -> BoxFuture<'_, Result<Vec<Container>, Error>>;
+
+    fn inspect(&self, id: &str) -> BoxFuture<'_, Result<ContainerDetails, Error>>;
+
+    /// Streams log lines of the container. Nothing happens until the stream is polled.
+    fn logs(&self, id: &str, options: &LogsOptions) -> BoxStream<'static, Result<LogLine, Error>>;
+}
+
+/// Picks a backend from `DECOM_BACKEND` (`api` or `cli`).
+/// Without it, the Engine API is used when the daemon socket of `DOCKER_HOST` is reachable.
+pub fn from_env() -> Result<Arc<dyn Backend>, Error> {
+    let host = || -> Result<Host, Error> {
+        match env::var("DOCKER_HOST") {
+            Ok(value) if !value.is_empty() => Host::parse(&value),
+            _ => Ok(Host::default()),
+        }
+    };
+
+    match env::var("DECOM_BACKEND").as_deref() {
+        Ok("cli") => Ok(Arc::new(DockerCli)),
+        Ok("api") => Ok(Arc::new(DockerApi::new(host()?))),
+        Ok(other) => Err(Error::InvalidConfig(format!("unknown backend: {}", other))),
+        Err(_) => match host() {
+            Ok(host) if host.is_reachable() => Ok(Arc::new(DockerApi::new(host))),
+            _ => Ok(Arc::new(DockerCli)),
+        },
+    }
+}
@@ -0,0 +1,164 @@
+use crate::backend::{Backend, ContainerFilter};
+use crate::docker_compose::{merge_service_names, Service, PROJECT_LABEL, SERVICE_LABEL};
+use crate::error::Error;
+use crate::project::Project;
+use std::{sync::Arc, time::Duration};
+use tokio::sync::mpsc;
+
+/// Change of the project's services, as seen by `Discovery`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ServiceEvent {
+    /// A service got its first container.
+    Added(Service),
+    /// All containers of the service have been removed.
+    Removed(String),
+    /// Replicas of the service were added, removed or changed state.
+    Changed(Service),
+}
+
+impl ServiceEvent {
+    pub fn service_name(&self) -> &str {
+        match self {
+            ServiceEvent::Added(service) | ServiceEvent::Changed(service) => &service.service_name,
+            ServiceEvent::Removed(name) => name,
+        }
+    }
+}
+
+/// Keeps track of the project's containers to tell which services come and go.
+pub struct Discovery {
+    backend: Arc<dyn Backend>,
+    filter: ContainerFilter,
+    services: Vec<Service>,
+}
+
+impl Discovery {
+    /// Starts from the services already known, e.g. from `docker_compose::services`.
+    pub fn new(backend: Arc<dyn Backend>, project: &Project, services: Vec<Service>) -> Self {
+        Discovery {
+            backend,
+            filter: ContainerFilter::default().label(PROJECT_LABEL, &project.name),
+            services,
+        }
+    }
+
+    pub fn services(&self) -> &[Service] {
+        &self.services
+    }
+
+    /// Lists the project's containers once and returns what changed since the last time.
+    pub async fn poll(&mut self) -> Result<Vec<ServiceEvent>, Error> {
+        let containers = self.backend.containers(&self.filter).await?;
+
+        // Known services keep their order, new ones are appended by name.
+        let mut names: Vec<String> = self
+            .services
+            .iter()
+            .map(|s| s.service_name.clone())
+            .collect();
+        let mut added: Vec<String> = containers
+            .iter()
+            .filter_map(|c| c.label(SERVICE_LABEL))
+            .filter(|name| !names.iter().any(|known| known == name))
+            .map(String::from)
+            .collect();
+        added.sort();
+        added.dedup();
+        names.extend(added);
+
+        let services = merge_service_names(names, containers);
+        let events = diff(&self.services, &services);
+        self.services = services;
+        Ok(events)
+    }
+
+    /// Polls in a task on the current Tokio runtime until the receiver is dropped.
+    /// Failed polls are retried on the next round.
+    pub fn watch(mut self, interval: Duration) -> mpsc::UnboundedReceiver<ServiceEvent> {
+        let (tx, rx) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(interval).await;
+                if tx.is_closed() {
+                    break;
+                }
+                if let Ok(events) = self.poll().await {
+                    for event in events {
+                        if tx.send(event).is_err() {
+                            return;
+                        }
+                    }
+                }
+            }
+        });
+        rx
+    }
+}
+
+fn diff(old: &[Service], new: &[Service]) -> Vec<ServiceEvent> {
+    let find = |services: &[Service], name: &str| {
+        services.iter().find(|s| s.service_name == name).cloned()
+    };
+
+    let removed = old
+        .iter()
+        .filter(|s| find(new, &s.service_name).is_none())
+        .map(|s| ServiceEvent::Removed(s.service_name.clone()));
+    let changed = new.iter().filter_map(|s| match find(old, &s.service_name) {
+        None => Some(ServiceEvent::Added(s.clone())),
+        Some(prev) if prev != *s => Some(ServiceEvent::Changed(s.clone())),
+        Some(_) => None,
+    });
+    removed.chain(changed).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::docker_compose::Replica;
+    use crate::multiline::Multiline;
+    use crate::parser::Format;
+    use crate::store::Retention;
+
+    fn service(name: &str, states: &[&str]) -> Service {
+        Service {
+            service_name: name.into(),
+            replicas: states
+                .iter()
+                .enumerate()
+                .map(|(i, state)| Replica {
+                    number: i as u32 + 1,
+                    container_name: format!("app_{}_{}", name, i + 1),
+                    container_id: format!("{}{}", name, i + 1),
+                    state: state.to_string(),
+                })
+                .collect(),
+            format: Format::Auto,
+            multiline: Multiline::Auto,
+            retention: Retention::default(),
+        }
+    }
+
+    #[test]
+    fn test_diff() {
+        let old = vec![
+            service("web", &["running"]),
+            service("db", &["running"]),
+            service("cache", &["running"]),
+        ];
+        let new = vec![
+            service("web", &["running"]),
+            service("db", &["exited"]),
+            service("worker", &["running", "running"]),
+        ];
+        assert_eq!(
+            diff(&old, &new),
+            vec![
+                ServiceEvent::Removed("cache".into()),
+                ServiceEvent::Changed(service("db", &["exited"])),
+                ServiceEvent::Added(service("worker", &["running", "running"])),
+            ]
+        );
+        assert_eq!(diff(&new, &new), vec![]);
+    }
+}
@@ -1,44 +1,209 @@
-use std::{collections::HashMap, error::Error, result::Result, str};
+use crate::backend::{Backend, ContainerFilter, LogLine, LogStream, LogsOptions};
+use crate::error::Error;
+use chrono::SecondsFormat;
+use futures::{
+    future::BoxFuture,
+    prelude::*,
+    stream::{self, BoxStream},
+};
+use serde::Deserialize;
+use std::{collections::HashMap, ffi::OsStr, process::Stdio, result::Result, str};
 use tokio::process::Command;
+use tokio_util::codec::{FramedRead, LinesCodec};
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Container {
     pub id: String,
     pub name: String,
+    pub labels: HashMap<String, String>,
+    /// Status such as `running` or `exited`, empty when unknown.
+    pub state: String,
 }
 
-pub async fn names(container_ids: Vec<String>) -> Result<Vec<Container>, Box<dyn Error>> {
+impl Container {
+    pub fn label(&self, key: &str) -> Option<&str> {
+        self.labels.get(key).map(String::as_str)
+    }
+}
+
+/// Subset of `docker inspect` output, shared by both backends.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct ContainerDetails {
+    pub id: String,
+    pub name: String,
+    pub state: ContainerState,
+    pub config: ContainerConfig,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct ContainerState {
+    pub status: String,
+    pub running: bool,
+    pub exit_code: i64,
+    pub started_at: String,
+    pub finished_at: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct ContainerConfig {
+    pub image: String,
+    #[serde(default)]
+    pub tty: bool,
+    pub labels: Option<HashMap<String, String>>,
+}
+
+impl From<ContainerDetails> for Container {
+    fn from(details: ContainerDetails) -> Self {
+        Container {
+            id: details.id,
+            name: details.name,
+            labels: details.config.labels.unwrap_or_default(),
+            state: details.state.status,
+        }
+    }
+}
+
+impl ContainerDetails {
+    pub(crate) fn from_json(json: &[u8]) -> Result<Self, serde_json::Error> {
+        let mut details: ContainerDetails = serde_json::from_slice(json)?;
+        details.name = details.name.trim_start_matches('/').to_string();
+        Ok(details)
+    }
+}
+
+/// Runs `docker` and returns its stdout, or an error telling why it failed.
+async fn docker<I, S>(args: I) -> Result<Vec<u8>, Error>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<OsStr>,
+{
     let output = Command::new("docker")
-        .args(&["ps", "-a", "--format", "{{.ID}},{{.Names}}", "--no-trunc"])
+        .args(args)
         .output()
-        .await?;
-    let output = str::from_utf8(output.stdout.as_slice())?;
-    let dict = parse_ps_result(output);
-    Ok(map_id_and_name(container_ids, dict))
+        .await
+        .map_err(|e| Error::from_spawn("docker", e))?;
+    if !output.status.success() {
+        return Err(Error::from_output("docker", &output));
+    }
+    Ok(output.stdout)
 }
 
-fn parse_ps_result(output: &str) -> HashMap<String, String> {
-    output
-        .lines()
+pub async fn list(filter: &ContainerFilter) -> Result<Vec<Container>, Error> {
+    let mut args = vec!["ps", "-a", "-q", "--no-trunc"]
         .into_iter()
-        .filter_map(|s| {
-            s.split_once(',')
-                .map(|(id, name)| (id.to_string(), name.to_string()))
-        })
-        .collect()
+        .map(String::from)
+        .collect::<Vec<_>>();
+    filter.labels.iter().for_each(|(key, value)| {
+        args.push("--filter".into());
+        args.push(format!("label={}={}", key, value));
+    });
+    let output = docker(&args).await?;
+    let ids: Vec<&str> = str::from_utf8(output.as_slice())?.lines().collect();
+    if ids.is_empty() {
+        return Ok(vec![]);
+    }
+
+    // `docker ps` can't print all labels unambiguously, so ask `docker inspect` for them.
+    let mut args = vec!["inspect", "--type", "container", "--format", "{{json .}}"];
+    args.extend(ids);
+    let output = docker(&args).await?;
+    let mut containers = str::from_utf8(output.as_slice())?
+        .lines()
+        .map(|line| ContainerDetails::from_json(line.as_bytes()).map(Container::from))
+        .collect::<Result<Vec<_>, _>>()?;
+    containers.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(containers)
 }
 
-fn map_id_and_name(ids: Vec<String>, dict: HashMap<String, String>) -> Vec<Container> {
-    ids.into_iter()
-        .map(|id| (id.clone(), dict.get(&id)))
-        .filter_map(|(id, name)| match name {
-            Some(name) => Some(Container {
-                id,
-                name: name.clone(),
-            }),
-            _ => None,
-        })
-        .collect()
+pub async fn inspect(id: &str) -> Result<ContainerDetails, Error> {
+    let output = docker(&[
+        "inspect",
+        "--type",
+        "container",
+        "--format",
+        "{{json .}}",
+        id,
+    ])
+    .await?;
+    Ok(ContainerDetails::from_json(&output)?)
+}
+
+/// Backend which shells out to the `docker` command.
+pub struct DockerCli;
+
+impl Backend for DockerCli {
+    fn containers(&self, filter: &ContainerFilter) -> BoxFuture<'_, Result<Vec<Container>, Error>> {
+        let filter = filter.clone();
+        async move { list(&filter).await }.boxed()
+    }
+
+    fn inspect(&self, id: &str) -> BoxFuture<'_, Result<ContainerDetails, Error>> {
+        let id = id.to_string();
+        async move { inspect(&id).await }.boxed()
+    }
+
+    fn logs(&self, id: &str, options: &LogsOptions) -> BoxStream<'static, Result<LogLine, Error>> {
+        let mut args = vec!["logs".to_string()];
+        if options.follow {
+            args.push("-f".into());
+        }
+        if options.timestamps {
+            args.push("--timestamps".into());
+        }
+        if let Some(since) = options.since {
+            args.push("--since".into());
+            args.push(since.to_rfc3339_opts(SecondsFormat::Nanos, true));
+        }
+        if let Some(until) = options.until {
+            args.push("--until".into());
+            args.push(until.to_rfc3339_opts(SecondsFormat::Nanos, true));
+        }
+        if let Some(tail) = options.tail {
+            args.push("--tail".into());
+            args.push(tail.to_string());
+        }
+        let timestamps = options.timestamps;
+        args.push(id.to_string());
+
+        let start = async move {
+            let mut child = Command::new("docker")
+                .args(&args)
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped())
+                .kill_on_drop(true)
+                .spawn()
+                .map_err(|e| Error::from_spawn("docker", e))?;
+            let stdout = child.stdout.take().expect("failed to get child output");
+            let stderr = child.stderr.take().expect("failed to get child output");
+
+            // `docker logs` replays the container's stderr on its own stderr.
+            let lines = stream::select(
+                FramedRead::new(stdout, LinesCodec::new())
+                    .map_ok(|text| LogLine::new(LogStream::Stdout, text)),
+                FramedRead::new(stderr, LinesCodec::new())
+                    .map_ok(|text| LogLine::new(LogStream::Stderr, text)),
+            )
+            .map_ok(move |line| {
+                if timestamps {
+                    line.with_timestamp()
+                } else {
+                    line
+                }
+            });
+
+            // The child is carried along so that it is killed when the stream is dropped.
+            let lines = stream::unfold((child, lines), |(child, mut lines)| async move {
+                let line = lines.next().await?;
+                Some((line.map_err(Error::from), (child, lines)))
+            });
+            Ok::<_, Error>(lines)
+        };
+
+        stream::once(start).try_flatten().boxed()
+    }
 }
 
 #[cfg(test)]
@@ -46,56 +211,22 @@ mod tests {
     use super::*;
 
     #[test]
-    fn test_parse_ps_result() {
-        let output = "7d7b045685ce0704de380ad30424cdcdde79448c6b78e967f69997db69678fc4,environments_apple_1\n\
-            f087f5679cea32b36414923f62d0d30b851cfeebbac46cac440068fcdc6043eb,environments_banana-api_1\n\
-            2e5aeea126fe2ce71c65501f428a2880664cd001fd6ec84cae688cec45a57794,environments_coconut-api_1";
-        let mut expected: HashMap<String, String> = HashMap::new();
-        expected.insert(
-            "7d7b045685ce0704de380ad30424cdcdde79448c6b78e967f69997db69678fc4".into(),
-            "environments_apple_1".into(),
-        );
-        expected.insert(
-            "f087f5679cea32b36414923f62d0d30b851cfeebbac46cac440068fcdc6043eb".into(),
-            "environments_banana-api_1".into(),
-        );
-        expected.insert(
-            "2e5aeea126fe2ce71c65501f428a2880664cd001fd6ec84cae688cec45a57794".into(),
-            "environments_coconut-api_1".into(),
-        );
-        assert_eq!(parse_ps_result(output), expected);
-    }
-
-    #[test]
-    fn test_map_id_and_name() {
-        let ids = vec![
-            "7d7b045685ce0704de380ad30424cdcdde79448c6b78e967f69997db69678fc4".into(),
-            "2e5aeea126fe2ce71c65501f428a2880664cd001fd6ec84cae688cec45a57794".into(),
-        ];
-        let mut dict: HashMap<String, String> = HashMap::new();
-        dict.insert(
-            "7d7b045685ce0704de380ad30424cdcdde79448c6b78e967f69997db69678fc4".into(),
-            "environments_apple_1".into(),
-        );
-        dict.insert(
-            "f087f5679cea32b36414923f62d0d30b851cfeebbac46cac440068fcdc6043eb".into(),
-            "environments_banana-api_1".into(),
-        );
-        dict.insert(
-            "2e5aeea126fe2ce71c65501f428a2880664cd001fd6ec84cae688cec45a57794".into(),
-            "environments_coconut-api_1".into(),
-        );
-
-        let expected: Vec<Container> = vec![
-            Container {
-                id: "7d7b045685ce0704de380ad30424cdcdde79448c6b78e967f69997db69678fc4".into(),
-                name: "environments_apple_1".into(),
-            },
-            Container {
-                id: "2e5aeea126fe2ce71c65501f428a2880664cd001fd6ec84cae688cec45a57794".into(),
-                name: "environments_coconut-api_1".into(),
+    fn test_details_from_json() {
+        let json = br#"{
+            "Id": "7d7b045685ce",
+            "Name": "/environments_apple_1",
+            "State": {
+                "Status": "exited",
+                "Running": false,
+                "ExitCode": 137,
+                "StartedAt": "2021-05-01T10:00:00.000000000Z",
+                "FinishedAt": "2021-05-01T11:00:00.000000000Z"
             },
-        ];
-        assert_eq!(map_id_and_name(ids, dict), expected);
+            "Config": { "Image": "apple:latest", "Tty": false, "Labels": null }
+        }"#;
+        let details = ContainerDetails::from_json(json).unwrap();
+        assert_eq!(details.name, "environments_apple_1");
+        assert_eq!(details.state.exit_code, 137);
+        assert_eq!(details.config.labels, None);
     }
 }
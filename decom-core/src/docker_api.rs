@@ -0,0 +1,697 @@
+use crate::backend::{Backend, ContainerFilter, LogLine, LogStream, LogsOptions};
+use crate::docker::{Container, ContainerDetails};
+use crate::error::Error;
+use futures::{
+    future::BoxFuture,
+    prelude::*,
+    stream::{self, BoxStream},
+};
+use serde::Deserialize;
+use std::{
+    collections::{HashMap, VecDeque},
+    io,
+    path::PathBuf,
+    result::Result,
+};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader},
+    net::{TcpStream, UnixStream},
+};
+
+const DEFAULT_SOCKET: &str = "/var/run/docker.sock";
+
+/// Address of the Docker daemon, parsed from `DOCKER_HOST`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Host {
+    Unix(PathBuf),
+    Tcp(String),
+}
+
+impl Default for Host {
+    fn default() -> Self {
+        Host::Unix(PathBuf::from(DEFAULT_SOCKET))
+    }
+}
+
+impl Host {
+    pub fn parse(value: &str) -> Result<Host, Error> {
+        if let Some(path) = value.strip_prefix("unix://") {
+            Ok(Host::Unix(PathBuf::from(path)))
+        } else if let Some(addr) = value.strip_prefix("tcp://") {
+            Ok(Host::Tcp(addr.trim_end_matches('/').to_string()))
+        } else {
+            Err(Error::InvalidConfig(format!(
+                "unsupported DOCKER_HOST: {}",
+                value
+            )))
+        }
+    }
+
+    pub fn is_reachable(&self) -> bool {
+        match self {
+            Host::Unix(path) => path.exists(),
+            Host::Tcp(_) => true,
+        }
+    }
+}
+
+trait Io: AsyncRead + AsyncWrite + Send + Unpin {}
+impl<T: AsyncRead + AsyncWrite + Send + Unpin> Io for T {}
+
+/// Backend which talks to the Docker Engine API over HTTP.
+pub struct DockerApi {
+    host: Host,
+}
+
+impl DockerApi {
+    pub fn new(host: Host) -> Self {
+        DockerApi { host }
+    }
+
+    async fn connect(&self) -> Result<Box<dyn Io>, Error> {
+        Ok(match &self.host {
+            Host::Unix(path) => Box::new(
+                UnixStream::connect(path)
+                    .await
+                    .map_err(|e| Error::from_connect(&path.display().to_string(), e))?,
+            ),
+            Host::Tcp(addr) => Box::new(
+                TcpStream::connect(addr.as_str())
+                    .await
+                    .map_err(|e| Error::from_connect(addr, e))?,
+            ),
+        })
+    }
+
+    async fn get(&self, path: &str) -> Result<Response, Error> {
+        let mut io = self.connect().await?;
+        let request = format!(
+            "GET {} HTTP/1.1\r\nHost: docker\r\nUser-Agent: decom\r\nConnection: close\r\n\r\n",
+            path
+        );
+        io.write_all(request.as_bytes()).await?;
+        io.flush().await?;
+
+        let response = Response::read(BufReader::new(io)).await?;
+        if response.status / 100 != 2 {
+            let status = response.status;
+            let body = response.bytes().await?;
+            return Err(api_error(status, &body));
+        }
+        Ok(response)
+    }
+
+    async fn get_json<T: serde::de::DeserializeOwned>(&self, path: &str) -> Result<T, Error> {
+        let body = self.get(path).await?.bytes().await?;
+        Ok(serde_json::from_slice(&body)?)
+    }
+
+    async fn inspect(&self, id: &str) -> Result<ContainerDetails, Error> {
+        let body = self
+            .get(&format!("/containers/{}/json", id))
+            .await?
+            .bytes()
+            .await?;
+        Ok(ContainerDetails::from_json(&body)?)
+    }
+}
+
+impl Backend for DockerApi {
+    fn containers(&self, filter: &ContainerFilter) -> BoxFuture<'_, Result<Vec<Container>, Error>> {
+        let mut path = "/containers/json?all=1".to_string();
+        if !filter.labels.is_empty() {
+            let labels: Vec<String> = filter
+                .labels
+                .iter()
+                .map(|(key, value)| format!("{}={}", key, value))
+                .collect();
+            let filters = serde_json::json!({ "label": labels }).to_string();
+            path.push_str(&format!("&filters={}", percent_encode(&filters)));
+        }
+
+        async move {
+            let summaries: Vec<ContainerSummary> = self.get_json(&path).await?;
+            let mut containers: Vec<Container> =
+                summaries.into_iter().map(Container::from).collect();
+            containers.sort_by(|a, b| a.name.cmp(&b.name));
+            Ok(containers)
+        }
+        .boxed()
+    }
+
+    fn inspect(&self, id: &str) -> BoxFuture<'_, Result<ContainerDetails, Error>> {
+        let id = id.to_string();
+        async move { DockerApi::inspect(self, &id).await }.boxed()
+    }
+
+    fn logs(&self, id: &str, options: &LogsOptions) -> BoxStream<'static, Result<LogLine, Error>> {
+        let api = DockerApi::new(self.host.clone());
+        let id = id.to_string();
+        let timestamps = options.timestamps;
+        let mut path = format!(
+            "/containers/{}/logs?stdout=1&stderr=1&follow={}&timestamps={}",
+            id,
+            if options.follow { 1 } else { 0 },
+            if timestamps { 1 } else { 0 }
+        );
+        let bounds = [("since", options.since), ("until", options.until)];
+        for (name, time) in bounds.iter() {
+            if let Some(time) = time {
+                path.push_str(&format!(
+                    "&{}={}.{:09}",
+                    name,
+                    time.timestamp(),
+                    time.timestamp_subsec_nanos()
+                ));
+            }
+        }
+        if let Some(tail) = options.tail {
+            path.push_str(&format!("&tail={}", tail));
+        }
+
+        let start = async move {
+            // The stream is only multiplexed when the container has no TTY.
+            let tty = api.inspect(&id).await?.config.tty;
+            let response = api.get(&path).await?;
+            Ok::<_, Error>((response, Demuxer::new(tty), VecDeque::new(), false))
+        };
+
+        stream::once(start)
+            .map_ok(|state| {
+                stream::try_unfold(
+                    state,
+                    |(mut response, mut demuxer, mut queue, mut done)| async move {
+                        loop {
+                            if let Some(line) = queue.pop_front() {
+                                return Ok(Some((line, (response, demuxer, queue, done))));
+                            }
+                            if done {
+                                return Ok(None);
+                            }
+                            match response.chunk().await? {
+                                Some(data) => queue.extend(demuxer.push(&data)),
+                                None => {
+                                    queue.extend(demuxer.finish());
+                                    done = true;
+                                }
+                            }
+                        }
+                    },
+                )
+            })
+            .try_flatten()
+            .map_ok(move |line| {
+                if timestamps {
+                    line.with_timestamp()
+                } else {
+                    line
+                }
+            })
+            .boxed()
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ContainerSummary {
+    id: String,
+    names: Vec<String>,
+    labels: Option<HashMap<String, String>>,
+    #[serde(default)]
+    state: String,
+}
+
+impl From<ContainerSummary> for Container {
+    fn from(summary: ContainerSummary) -> Self {
+        let name = summary
+            .names
+            .first()
+            .map(|name| name.trim_start_matches('/').to_string())
+            .unwrap_or_default();
+        Container {
+            id: summary.id,
+            name,
+            labels: summary.labels.unwrap_or_default(),
+            state: summary.state,
+        }
+    }
+}
+
+fn percent_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+fn api_error(status: u16, body: &[u8]) -> Error {
+    #[derive(Deserialize)]
+    struct ErrorBody {
+        message: String,
+    }
+    let message = serde_json::from_slice::<ErrorBody>(body)
+        .map(|error| error.message)
+        .unwrap_or_default();
+    match status {
+        404 => Error::NoSuchContainer(message),
+        _ => Error::Api { status, message },
+    }
+}
+
+enum Framing {
+    Length(u64),
+    Chunked(u64),
+    Eof,
+}
+
+struct Response {
+    status: u16,
+    reader: BufReader<Box<dyn Io>>,
+    framing: Framing,
+    finished: bool,
+}
+
+impl Response {
+    async fn read(mut reader: BufReader<Box<dyn Io>>) -> Result<Response, Error> {
+        let mut line = String::new();
+        reader.read_line(&mut line).await?;
+        let status = line
+            .split_whitespace()
+            .nth(1)
+            .and_then(|s| s.parse::<u16>().ok())
+            .ok_or_else(|| Error::Parse(format!("malformed status line: {:?}", line.trim_end())))?;
+
+        let mut framing = Framing::Eof;
+        loop {
+            line.clear();
+            if reader.read_line(&mut line).await? == 0 {
+                return Err(Error::Parse(
+                    "connection closed while reading headers".to_string(),
+                ));
+            }
+            let header = line.trim_end();
+            if header.is_empty() {
+                break;
+            }
+            if let Some((name, value)) = header.split_once(':') {
+                let value = value.trim();
+                if name.eq_ignore_ascii_case("content-length") {
+                    framing = Framing::Length(value.parse().map_err(|_| {
+                        Error::Parse(format!("malformed content length: {:?}", value))
+                    })?);
+                } else if name.eq_ignore_ascii_case("transfer-encoding")
+                    && value.eq_ignore_ascii_case("chunked")
+                {
+                    framing = Framing::Chunked(0);
+                }
+            }
+        }
+
+        Ok(Response {
+            status,
+            reader,
+            framing,
+            finished: false,
+        })
+    }
+
+    /// Returns the next piece of the body, or `None` at the end of it.
+    async fn chunk(&mut self) -> io::Result<Option<Vec<u8>>> {
+        if self.finished {
+            return Ok(None);
+        }
+
+        let mut buf = vec![0u8; 8192];
+        let n = match &mut self.framing {
+            Framing::Length(remaining) => {
+                if *remaining == 0 {
+                    0
+                } else {
+                    let max = (*remaining).min(buf.len() as u64) as usize;
+                    let n = self.reader.read(&mut buf[..max]).await?;
+                    *remaining -= n as u64;
+                    n
+                }
+            }
+            Framing::Chunked(remaining) => {
+                if *remaining == 0 {
+                    let mut line = String::new();
+                    self.reader.read_line(&mut line).await?;
+                    let size = line.trim().split(';').next().unwrap_or("");
+                    *remaining = u64::from_str_radix(size, 16).map_err(|_| {
+                        io::Error::new(io::ErrorKind::InvalidData, "malformed chunk size")
+                    })?;
+                    if *remaining == 0 {
+                        // Skip trailers up to the terminating empty line
+                        loop {
+                            line.clear();
+                            if self.reader.read_line(&mut line).await? == 0
+                                || line.trim_end().is_empty()
+                            {
+                                break;
+                            }
+                        }
+                    }
+                }
+                if *remaining == 0 {
+                    0
+                } else {
+                    let max = (*remaining).min(buf.len() as u64) as usize;
+                    let n = self.reader.read(&mut buf[..max]).await?;
+                    if n == 0 {
+                        return Err(io::ErrorKind::UnexpectedEof.into());
+                    }
+                    *remaining -= n as u64;
+                    if *remaining == 0 {
+                        let mut crlf = [0u8; 2];
+                        self.reader.read_exact(&mut crlf).await?;
+                    }
+                    n
+                }
+            }
+            Framing::Eof => self.reader.read(&mut buf).await?,
+        };
+
+        if n == 0 {
+            self.finished = true;
+            Ok(None)
+        } else {
+            buf.truncate(n);
+            Ok(Some(buf))
+        }
+    }
+
+    async fn bytes(mut self) -> io::Result<Vec<u8>> {
+        let mut body = vec![];
+        while let Some(chunk) = self.chunk().await? {
+            body.extend(chunk);
+        }
+        Ok(body)
+    }
+}
+
+/// Splits the log stream into lines, decoding the 8-byte frame headers of a multiplexed stream.
+struct Demuxer {
+    tty: bool,
+    pending: Vec<u8>,
+    lines: [Vec<u8>; 2],
+}
+
+impl Demuxer {
+    fn new(tty: bool) -> Self {
+        Demuxer {
+            tty,
+            pending: vec![],
+            lines: [vec![], vec![]],
+        }
+    }
+
+    fn push(&mut self, data: &[u8]) -> Vec<LogLine> {
+        let mut out = vec![];
+        if self.tty {
+            split_lines(&mut self.lines[0], data, LogStream::Stdout, &mut out);
+            return out;
+        }
+
+        self.pending.extend_from_slice(data);
+        let mut offset = 0;
+        while self.pending.len() - offset >= 8 {
+            let header = &self.pending[offset..offset + 8];
+            let size = u32::from_be_bytes([header[4], header[5], header[6], header[7]]) as usize;
+            if self.pending.len() - offset - 8 < size {
+                break;
+            }
+            let (index, stream) = match header[0] {
+                2 => (1, LogStream::Stderr),
+                _ => (0, LogStream::Stdout),
+            };
+            let payload = &self.pending[offset + 8..offset + 8 + size];
+            split_lines(&mut self.lines[index], payload, stream, &mut out);
+            offset += 8 + size;
+        }
+        self.pending.drain(..offset);
+        out
+    }
+
+    fn finish(&mut self) -> Vec<LogLine> {
+        let streams = [LogStream::Stdout, LogStream::Stderr];
+        self.lines
+            .iter_mut()
+            .zip(streams.iter())
+            .filter(|(line, _)| !line.is_empty())
+            .map(|(line, stream)| {
+                LogLine::new(
+                    *stream,
+                    String::from_utf8_lossy(&std::mem::take(line)).into_owned(),
+                )
+            })
+            .collect()
+    }
+}
+
+fn split_lines(line: &mut Vec<u8>, data: &[u8], stream: LogStream, out: &mut Vec<LogLine>) {
+    for &b in data {
+        if b == b'\n' {
+            if line.last() == Some(&b'\r') {
+                line.pop();
+            }
+            out.push(LogLine::new(
+                stream,
+                String::from_utf8_lossy(line).into_owned(),
+            ));
+            line.clear();
+        } else {
+            line.push(b);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use tokio::net::UnixListener;
+
+    fn frame(stream: u8, payload: &str) -> Vec<u8> {
+        let mut frame = vec![stream, 0, 0, 0];
+        frame.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+        frame.extend_from_slice(payload.as_bytes());
+        frame
+    }
+
+    fn chunked(parts: &[Vec<u8>]) -> Vec<u8> {
+        let mut body = vec![];
+        for part in parts {
+            body.extend_from_slice(format!("{:x}\r\n", part.len()).as_bytes());
+            body.extend_from_slice(part);
+            body.extend_from_slice(b"\r\n");
+        }
+        body.extend_from_slice(b"0\r\n\r\n");
+        body
+    }
+
+    /// Serves canned responses keyed by request path, one connection per request.
+    async fn serve(name: &str, routes: Vec<(&'static str, Vec<u8>)>) -> DockerApi {
+        let path = env::temp_dir().join(format!("decom-{}-{}.sock", name, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let routes = routes.clone();
+                tokio::spawn(async move {
+                    let mut reader = BufReader::new(stream);
+                    let mut request = String::new();
+                    reader.read_line(&mut request).await.unwrap();
+                    let mut line = String::new();
+                    while reader.read_line(&mut line).await.unwrap() > 2 {
+                        line.clear();
+                    }
+                    let target = request.split_whitespace().nth(1).unwrap().to_string();
+                    let response = routes
+                        .iter()
+                        .find(|(route, _)| target == *route)
+                        .map(|(_, response)| response.clone())
+                        .unwrap_or_else(|| {
+                            let body = r#"{"message":"no such container"}"#;
+                            format!(
+                                "HTTP/1.1 404 Not Found\r\nContent-Length: {}\r\n\r\n{}",
+                                body.len(),
+                                body
+                            )
+                            .into_bytes()
+                        });
+                    let mut stream = reader.into_inner();
+                    stream.write_all(&response).await.unwrap();
+                    stream.shutdown().await.unwrap();
+                });
+            }
+        });
+        DockerApi::new(Host::Unix(path))
+    }
+
+    fn ok(headers: &str, body: &[u8]) -> Vec<u8> {
+        let mut response = format!("HTTP/1.1 200 OK\r\n{}\r\n", headers).into_bytes();
+        response.extend_from_slice(body);
+        response
+    }
+
+    const INSPECT: &str = r#"{"Id":"abc","Name":"/app_web_1","State":{"Status":"running","Running":true,"ExitCode":0,"StartedAt":"","FinishedAt":""},"Config":{"Image":"web","Tty":false,"Labels":{}}}"#;
+
+    #[test]
+    fn test_host_parse() {
+        assert_eq!(
+            Host::parse("unix:///run/user/1000/docker.sock").unwrap(),
+            Host::Unix("/run/user/1000/docker.sock".into())
+        );
+        assert_eq!(
+            Host::parse("tcp://127.0.0.1:2375").unwrap(),
+            Host::Tcp("127.0.0.1:2375".into())
+        );
+        assert!(Host::parse("npipe:////./pipe/docker_engine").is_err());
+    }
+
+    #[test]
+    fn test_demuxer() {
+        let mut demuxer = Demuxer::new(false);
+        let mut data = frame(1, "hello\nwor");
+        data.extend(frame(2, "oops\n"));
+        data.extend(frame(1, "ld\n"));
+        data.extend(frame(1, "tail"));
+
+        // Feed byte by byte to exercise frames split across chunks
+        let lines: Vec<LogLine> = data.iter().flat_map(|b| demuxer.push(&[*b])).collect();
+        assert_eq!(
+            lines,
+            vec![
+                LogLine::new(LogStream::Stdout, "hello".into()),
+                LogLine::new(LogStream::Stderr, "oops".into()),
+                LogLine::new(LogStream::Stdout, "world".into()),
+            ]
+        );
+        assert_eq!(
+            demuxer.finish(),
+            vec![LogLine::new(LogStream::Stdout, "tail".into())]
+        );
+    }
+
+    #[test]
+    fn test_demuxer_tty() {
+        let mut demuxer = Demuxer::new(true);
+        assert_eq!(
+            demuxer.push(b"one\r\ntwo"),
+            vec![LogLine::new(LogStream::Stdout, "one".into())]
+        );
+    }
+
+    #[test]
+    fn test_percent_encode() {
+        assert_eq!(
+            percent_encode(r#"{"label":["a=b c"]}"#),
+            "%7B%22label%22%3A%5B%22a%3Db%20c%22%5D%7D"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_containers() {
+        let body = br#"[
+            {"Id":"b2","Names":["/app_worker_1"],"Labels":{"com.docker.compose.project":"app"},"State":"exited"},
+            {"Id":"a1","Names":["/app_api_1"],"Labels":null}
+        ]"#;
+        let api = serve(
+            "containers",
+            vec![(
+                "/containers/json?all=1&filters=%7B%22label%22%3A%5B%22com.docker.compose.project%3Dapp%22%5D%7D",
+                ok(&format!("Content-Length: {}\r\n", body.len()), body),
+            )],
+        )
+        .await;
+        let filter = ContainerFilter::default().label("com.docker.compose.project", "app");
+        let mut labels = HashMap::new();
+        labels.insert("com.docker.compose.project".into(), "app".into());
+        assert_eq!(
+            api.containers(&filter).await.unwrap(),
+            vec![
+                Container {
+                    id: "a1".into(),
+                    name: "app_api_1".into(),
+                    labels: HashMap::new(),
+                    state: String::new(),
+                },
+                Container {
+                    id: "b2".into(),
+                    name: "app_worker_1".into(),
+                    labels,
+                    state: "exited".into(),
+                },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_inspect_not_found() {
+        let api = serve("inspect", vec![]).await;
+        let err = Backend::inspect(&api, "missing").await.unwrap_err();
+        assert!(matches!(err, Error::NoSuchContainer(_)));
+        assert_eq!(err.to_string(), "no such container");
+    }
+
+    #[tokio::test]
+    async fn test_logs() {
+        let body = chunked(&[
+            [
+                frame(1, "2021-05-01T10:00:00.000000001Z first\n"),
+                frame(2, "2021-05-01T10:00:00.000000002Z sec"),
+            ]
+            .concat(),
+            [
+                frame(2, "ond\n"),
+                frame(1, "2021-05-01T10:00:00.000000003Z third"),
+            ]
+            .concat(),
+        ]);
+        let api = serve(
+            "logs",
+            vec![
+                (
+                    "/containers/abc/json",
+                    ok(
+                        &format!("Content-Length: {}\r\n", INSPECT.len()),
+                        INSPECT.as_bytes(),
+                    ),
+                ),
+                (
+                    "/containers/abc/logs?stdout=1&stderr=1&follow=1&timestamps=1",
+                    ok("Transfer-Encoding: chunked\r\n", &body),
+                ),
+            ],
+        )
+        .await;
+
+        let options = LogsOptions {
+            follow: true,
+            timestamps: true,
+            since: None,
+            until: None,
+            tail: None,
+        };
+        let lines: Vec<LogLine> = api.logs("abc", &options).try_collect().await.unwrap();
+        assert!(lines.iter().all(|line| line.timestamp.is_some()));
+        let lines: Vec<LogLine> = lines
+            .into_iter()
+            .map(|line| LogLine::new(line.stream, line.text))
+            .collect();
+        assert_eq!(
+            lines,
+            vec![
+                LogLine::new(LogStream::Stdout, "first".into()),
+                LogLine::new(LogStream::Stderr, "second".into()),
+                LogLine::new(LogStream::Stdout, "third".into()),
+            ]
+        );
+    }
+}
@@ -1,53 +1,124 @@
-use crate::docker::{self, Container};
-use std::{error::Error, result::Result, str};
+use crate::backend::{Backend, ContainerFilter};
+use crate::docker::Container;
+use crate::error::Error;
+use crate::multiline::Multiline;
+use crate::parser::Format;
+use crate::project::Project;
+use crate::store::Retention;
+use std::{result::Result, str};
 use tokio::process::Command;
 
-pub async fn containers() -> Result<Vec<String>, Box<dyn Error>> {
+/// Runs `docker-compose` for the project and returns its stdout.
+async fn compose(project: &Project, args: &[&str]) -> Result<Vec<u8>, Error> {
     let output = Command::new("docker-compose")
-        .args(&["ps", "-q"])
-        .current_dir("/home/kodama/work/kuy/periodic-output")
+        .args(project.args())
+        .args(args)
+        .current_dir(&project.directory)
         .output()
-        .await?;
-    Ok(parse_lines(str::from_utf8(output.stdout.as_slice())?))
+        .await
+        .map_err(|e| Error::from_spawn("docker-compose", e))?;
+    if !output.status.success() {
+        return Err(match Error::from_output("docker-compose", &output) {
+            Error::NotComposeProject(_) => Error::NotComposeProject(project.directory.clone()),
+            e => e,
+        });
+    }
+    Ok(output.stdout)
 }
 
 fn parse_lines(output: &str) -> Vec<String> {
-    output.lines().into_iter().map(|s| s.to_string()).collect()
+    output.lines().map(|s| s.to_string()).collect()
 }
 
-#[derive(Debug, PartialEq)]
-pub struct Service {
-    pub service_name: String,
+pub const PROJECT_LABEL: &str = "com.docker.compose.project";
+pub const SERVICE_LABEL: &str = "com.docker.compose.service";
+pub const NUMBER_LABEL: &str = "com.docker.compose.container-number";
+pub const ONEOFF_LABEL: &str = "com.docker.compose.oneoff";
+/// Label telling the format of a service's log lines, such as `logfmt`.
+pub const FORMAT_LABEL: &str = "decom.format";
+/// Label telling how lines are joined into records: `auto`, `off` or a pattern of first lines.
+pub const MULTILINE_LABEL: &str = "decom.multiline";
+/// Label telling how much of the log is held in memory, e.g. `50000` lines or `32MiB`.
+pub const RETENTION_LABEL: &str = "decom.retention";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Replica {
+    pub number: u32,
     pub container_name: String,
     pub container_id: String,
+    pub state: String,
 }
 
-pub async fn services() -> Result<Vec<Service>, Box<dyn Error>> {
-    let output = Command::new("docker-compose")
-        .args(&["config", "--services"])
-        .current_dir("/home/kodama/work/kuy/periodic-output")
-        .output()
-        .await?;
-    let service_names = parse_lines(str::from_utf8(output.stdout.as_slice())?);
+#[derive(Debug, Clone, PartialEq)]
+pub struct Service {
+    pub service_name: String,
+    pub replicas: Vec<Replica>,
+    /// Format of the log lines, from the `decom.format` label of its containers.
+    pub format: Format,
+    /// How lines are joined, from the `decom.multiline` label of its containers.
+    pub multiline: Multiline,
+    /// How much of the log is held, from the `decom.retention` label of its containers.
+    pub retention: Retention,
+}
 
-    let ids = containers().await?;
-    let containers = docker::names(ids).await?;
+pub async fn services(project: &Project, backend: &dyn Backend) -> Result<Vec<Service>, Error> {
+    let output = compose(project, &["config", "--services"]).await?;
+    let service_names = parse_lines(str::from_utf8(output.as_slice())?);
+
+    let filter = ContainerFilter::default().label(PROJECT_LABEL, &project.name);
+    let containers = backend.containers(&filter).await?;
 
     Ok(merge_service_names(service_names, containers))
 }
 
-fn merge_service_names(service_names: Vec<String>, containers: Vec<Container>) -> Vec<Service> {
+pub(crate) fn merge_service_names(
+    service_names: Vec<String>,
+    containers: Vec<Container>,
+) -> Vec<Service> {
     service_names
         .into_iter()
         .filter_map(|service_name| {
-            let container = containers.iter().find(|&c| c.name.contains(&service_name));
-            match container {
-                Some(c) => Some(Service {
-                    service_name: service_name.clone(),
+            let containers: Vec<&Container> = containers
+                .iter()
+                .filter(|c| c.label(SERVICE_LABEL) == Some(service_name.as_str()))
+                .filter(|c| c.label(ONEOFF_LABEL) != Some("True"))
+                .collect();
+            let format = containers
+                .iter()
+                .find_map(|c| c.label(FORMAT_LABEL).and_then(|f| f.parse().ok()))
+                .unwrap_or_default();
+            let multiline = containers
+                .iter()
+                .find_map(|c| c.label(MULTILINE_LABEL).and_then(|m| m.parse().ok()))
+                .unwrap_or_default();
+            let retention = containers
+                .iter()
+                .find_map(|c| c.label(RETENTION_LABEL).and_then(|r| r.parse().ok()))
+                .unwrap_or_default();
+            let mut replicas: Vec<Replica> = containers
+                .iter()
+                .map(|c| Replica {
+                    number: c
+                        .label(NUMBER_LABEL)
+                        .and_then(|n| n.parse().ok())
+                        .unwrap_or(1),
                     container_name: c.name.clone(),
                     container_id: c.id.clone(),
-                }),
-                _ => None,
+                    state: c.state.clone(),
+                })
+                .collect();
+            replicas.sort_by_key(|r| r.number);
+
+            if replicas.is_empty() {
+                None
+            } else {
+                Some(Service {
+                    service_name,
+                    replicas,
+                    format,
+                    multiline,
+                    retention,
+                })
             }
         })
         .collect()
@@ -56,6 +127,7 @@ fn merge_service_names(service_names: Vec<String>, containers: Vec<Container>) -
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::collections::HashMap;
 
     #[test]
     fn test_parse_ps_result() {
@@ -70,31 +142,63 @@ mod tests {
         assert_eq!(parse_lines(output), expected);
     }
 
+    fn container(id: &str, name: &str, service: &str, number: &str) -> Container {
+        let mut labels = HashMap::new();
+        labels.insert(PROJECT_LABEL.to_string(), "parent-dir".to_string());
+        labels.insert(SERVICE_LABEL.to_string(), service.to_string());
+        labels.insert(NUMBER_LABEL.to_string(), number.to_string());
+        Container {
+            id: id.into(),
+            name: name.into(),
+            labels,
+            state: "running".into(),
+        }
+    }
+
+    fn replica(number: u32, id: &str, name: &str) -> Replica {
+        Replica {
+            number,
+            container_id: id.into(),
+            container_name: name.into(),
+            state: "running".into(),
+        }
+    }
+
     #[test]
     fn test_merge_service_names() {
-        let service_names = vec!["service1".into(), "service2".into(), "service3".into()];
+        let service_names = vec!["api-worker".into(), "api".into(), "db".into()];
+        let mut oneoff = container("f0", "parent-dir_api_run_1", "api", "1");
+        oneoff.labels.insert(ONEOFF_LABEL.into(), "True".into());
+        let mut api = container("b1", "parent-dir-api-1", "api", "1");
+        api.labels.insert(FORMAT_LABEL.into(), "logfmt".into());
+        api.labels.insert(MULTILINE_LABEL.into(), "off".into());
+        api.labels.insert(RETENTION_LABEL.into(), "500".into());
         let containers = vec![
-            Container {
-                id: "98d9a30d2e5676539dd92ef986ed2159e85af80ab77eaf2ac6f311a71ecca458".into(),
-                name: "parent-dir_service1_1".into(),
-            },
-            Container {
-                id: "88c904a13f039e3eca4ff145cb06d8244548a341e56212f7e43598c2c4f62e8a".into(),
-                name: "parent-dir_service2_1".into(),
-            },
+            container("a2", "parent-dir-api-worker-2", "api-worker", "2"),
+            container("a1", "parent-dir-api-worker-1", "api-worker", "1"),
+            api,
+            oneoff,
         ];
         let expected = vec![
             Service {
-                service_name: "service1".into(),
-                container_id: "98d9a30d2e5676539dd92ef986ed2159e85af80ab77eaf2ac6f311a71ecca458"
-                    .into(),
-                container_name: "parent-dir_service1_1".into(),
+                service_name: "api-worker".into(),
+                replicas: vec![
+                    replica(1, "a1", "parent-dir-api-worker-1"),
+                    replica(2, "a2", "parent-dir-api-worker-2"),
+                ],
+                format: Format::Auto,
+                multiline: Multiline::Auto,
+                retention: Retention::default(),
             },
             Service {
-                service_name: "service2".into(),
-                container_id: "88c904a13f039e3eca4ff145cb06d8244548a341e56212f7e43598c2c4f62e8a"
-                    .into(),
-                container_name: "parent-dir_service2_1".into(),
+                service_name: "api".into(),
+                replicas: vec![replica(1, "b1", "parent-dir-api-1")],
+                format: Format::Logfmt,
+                multiline: Multiline::Off,
+                retention: Retention {
+                    max_lines: Some(500),
+                    ..Retention::default()
+                },
             },
         ];
         assert_eq!(merge_service_names(service_names, containers), expected);
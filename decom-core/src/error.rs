@@ -0,0 +1,221 @@
+use std::{fmt, io, path::PathBuf, process::Output, str::Utf8Error};
+use tokio_util::codec::LinesCodecError;
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Everything that can go wrong talking to Docker or reading a project.
+#[derive(Debug)]
+pub enum Error {
+    /// The program, `docker` or `docker-compose`, isn't installed or not on `PATH`.
+    CommandNotFound(String),
+    /// The Docker daemon isn't running or can't be reached at the address.
+    DaemonUnavailable(String),
+    /// Access to the daemon socket at the address was denied.
+    PermissionDenied(String),
+    /// No compose file was found in the directory or any parent.
+    NotComposeProject(PathBuf),
+    /// The container doesn't exist, with Docker's message.
+    NoSuchContainer(String),
+    /// A command exited with failure for another reason.
+    Command {
+        program: String,
+        code: Option<i32>,
+        stderr: String,
+    },
+    /// The Engine API answered with an error status.
+    Api {
+        status: u16,
+        message: String,
+    },
+    /// Docker's output wasn't what we expected.
+    Parse(String),
+    InvalidConfig(String),
+    /// A search pattern isn't a valid regular expression.
+    InvalidPattern(String),
+    /// A filter expression couldn't be parsed.
+    InvalidFilter(String),
+    Io(io::Error),
+}
+
+impl Error {
+    /// Classifies a failed run of the program from what it printed to stderr.
+    pub(crate) fn from_output(program: &str, output: &Output) -> Error {
+        let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+        let lower = stderr.to_lowercase();
+        if lower.contains("permission denied") {
+            Error::PermissionDenied(stderr)
+        } else if lower.contains("cannot connect to the docker daemon")
+            || lower.contains("is the docker daemon running")
+        {
+            Error::DaemonUnavailable(stderr)
+        } else if lower.contains("no such container") || lower.contains("no such object") {
+            Error::NoSuchContainer(stderr)
+        } else if lower.contains("no configuration file provided")
+            || lower.contains("can't find a suitable configuration file")
+        {
+            Error::NotComposeProject(PathBuf::new())
+        } else {
+            Error::Command {
+                program: program.to_string(),
+                code: output.status.code(),
+                stderr,
+            }
+        }
+    }
+
+    /// Converts a failure to start the program.
+    pub(crate) fn from_spawn(program: &str, e: io::Error) -> Error {
+        match e.kind() {
+            io::ErrorKind::NotFound => Error::CommandNotFound(program.to_string()),
+            _ => Error::Io(e),
+        }
+    }
+
+    /// Converts a failure to connect to the daemon at the address.
+    pub(crate) fn from_connect(address: &str, e: io::Error) -> Error {
+        match e.kind() {
+            io::ErrorKind::NotFound | io::ErrorKind::ConnectionRefused => {
+                Error::DaemonUnavailable(address.to_string())
+            }
+            io::ErrorKind::PermissionDenied => Error::PermissionDenied(address.to_string()),
+            _ => Error::Io(e),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::CommandNotFound(program) => write!(
+                f,
+                "`{}` was not found, install it or add it to PATH",
+                program
+            ),
+            Error::DaemonUnavailable(detail) => write!(
+                f,
+                "cannot connect to the Docker daemon ({}), is it running?",
+                detail
+            ),
+            Error::PermissionDenied(detail) => write!(
+                f,
+                "permission denied on the Docker daemon ({}), add your user to the `docker` group or set DOCKER_HOST",
+                detail
+            ),
+            Error::NotComposeProject(dir) if dir.as_os_str().is_empty() => {
+                write!(f, "no compose file found, pass one with -f")
+            }
+            Error::NotComposeProject(dir) => write!(
+                f,
+                "no compose file found in {} or its parents, pass one with -f",
+                dir.display()
+            ),
+            Error::NoSuchContainer(message) => write!(f, "{}", message),
+            Error::Command {
+                program,
+                code,
+                stderr,
+            } => {
+                match code {
+                    Some(code) => write!(f, "`{}` exited with {}", program, code)?,
+                    None => write!(f, "`{}` was killed", program)?,
+                }
+                if !stderr.is_empty() {
+                    write!(f, ": {}", stderr)?;
+                }
+                Ok(())
+            }
+            Error::Api { status, message } => {
+                write!(f, "Docker API error {}: {}", status, message)
+            }
+            Error::Parse(message) => write!(f, "unexpected output from Docker: {}", message),
+            Error::InvalidConfig(message) => write!(f, "{}", message),
+            Error::InvalidPattern(message) => write!(f, "invalid pattern: {}", message),
+            Error::InvalidFilter(message) => write!(f, "invalid filter: {}", message),
+            Error::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::Parse(e.to_string())
+    }
+}
+
+impl From<Utf8Error> for Error {
+    fn from(e: Utf8Error) -> Self {
+        Error::Parse(e.to_string())
+    }
+}
+
+impl From<regex::Error> for Error {
+    fn from(e: regex::Error) -> Self {
+        Error::InvalidPattern(e.to_string())
+    }
+}
+
+impl From<LinesCodecError> for Error {
+    fn from(e: LinesCodecError) -> Self {
+        match e {
+            LinesCodecError::Io(e) => Error::Io(e),
+            e => Error::Parse(e.to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::process::ExitStatusExt;
+    use std::process::ExitStatus;
+
+    fn output(code: i32, stderr: &str) -> Output {
+        Output {
+            status: ExitStatus::from_raw(code << 8),
+            stdout: vec![],
+            stderr: stderr.as_bytes().to_vec(),
+        }
+    }
+
+    #[test]
+    fn test_from_output() {
+        let e = Error::from_output("docker", &output(1, "Cannot connect to the Docker daemon at unix:///var/run/docker.sock. Is the docker daemon running?\n"));
+        assert!(matches!(e, Error::DaemonUnavailable(_)));
+
+        let e = Error::from_output("docker", &output(1, "Got permission denied while trying to connect to the Docker daemon socket at unix:///var/run/docker.sock"));
+        assert!(matches!(e, Error::PermissionDenied(_)));
+
+        let e = Error::from_output("docker", &output(1, "Error: No such object: nope"));
+        assert!(matches!(e, Error::NoSuchContainer(_)));
+
+        let e = Error::from_output("docker", &output(125, "unknown flag: --nope\n"));
+        assert_eq!(
+            e.to_string(),
+            "`docker` exited with 125: unknown flag: --nope"
+        );
+    }
+
+    #[test]
+    fn test_from_spawn() {
+        let e = Error::from_spawn("docker-compose", io::Error::from(io::ErrorKind::NotFound));
+        assert_eq!(
+            e.to_string(),
+            "`docker-compose` was not found, install it or add it to PATH"
+        );
+    }
+}
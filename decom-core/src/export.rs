@@ -0,0 +1,248 @@
+use crate::backend::LogStream;
+use crate::parser::Format;
+use crate::record::LogRecord;
+use chrono::SecondsFormat;
+use serde_json::{json, Map, Value};
+use std::{fmt, io, io::Write, path::Path, str::FromStr};
+
+/// File format of exported records.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// A JSON object per line, with the fields parsed from the line.
+    Ndjson,
+    Csv,
+    /// Lines as `docker-compose logs --timestamps` writes them.
+    Text,
+}
+
+impl ExportFormat {
+    /// Format going by the extension of the path, e.g. `.csv`.
+    pub fn from_path(path: &Path) -> Option<ExportFormat> {
+        match path.extension()?.to_str()? {
+            "ndjson" | "jsonl" | "json" => Some(ExportFormat::Ndjson),
+            "csv" => Some(ExportFormat::Csv),
+            "txt" | "log" => Some(ExportFormat::Text),
+            _ => None,
+        }
+    }
+}
+
+impl FromStr for ExportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "ndjson" | "jsonl" | "json" => Ok(ExportFormat::Ndjson),
+            "csv" => Ok(ExportFormat::Csv),
+            "text" | "txt" => Ok(ExportFormat::Text),
+            _ => Err(format!(
+                "unknown export format `{}`, expected ndjson, csv or text",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for ExportFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ExportFormat::Ndjson => "ndjson",
+            ExportFormat::Csv => "csv",
+            ExportFormat::Text => "text",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+const CSV_HEADER: &str = "timestamp,service,container,replica,stream,level,msg,text";
+
+/// Writes records of services to a file in an `ExportFormat`.
+pub struct Exporter<W: Write> {
+    out: W,
+    format: ExportFormat,
+    count: usize,
+}
+
+impl<W: Write> Exporter<W> {
+    pub fn new(mut out: W, format: ExportFormat) -> io::Result<Self> {
+        if format == ExportFormat::Csv {
+            writeln!(out, "{}", CSV_HEADER)?;
+        }
+        Ok(Exporter {
+            out,
+            format,
+            count: 0,
+        })
+    }
+
+    /// Writes a record of the service, parsing its line in the format.
+    pub fn write(&mut self, service: &str, record: &LogRecord, format: Format) -> io::Result<()> {
+        let timestamp = record
+            .timestamp
+            .to_rfc3339_opts(SecondsFormat::AutoSi, true);
+        let structured = match record.stream {
+            LogStream::Event => None,
+            _ => record.parse_as(format),
+        };
+        let level = structured
+            .as_ref()
+            .and_then(|s| s.level)
+            .map(|level| format!("{:?}", level).to_lowercase());
+        match self.format {
+            ExportFormat::Ndjson => {
+                let mut object = Map::new();
+                object.insert("timestamp".into(), json!(timestamp));
+                object.insert("service".into(), json!(service));
+                object.insert("container".into(), json!(&*record.container));
+                object.insert("replica".into(), json!(record.replica));
+                object.insert("stream".into(), json!(stream(record.stream)));
+                object.insert("raw".into(), json!(record.raw));
+                if let Some(structured) = structured {
+                    object.insert("format".into(), json!(structured.format.to_string()));
+                    if let Some(level) = level {
+                        object.insert("level".into(), json!(level));
+                    }
+                    if let Some(msg) = structured.msg {
+                        object.insert("msg".into(), json!(msg));
+                    }
+                    if let Some(time) = structured.time {
+                        let time = time.to_rfc3339_opts(SecondsFormat::AutoSi, true);
+                        object.insert("time".into(), json!(time));
+                    }
+                    let fields: Map<String, Value> = structured
+                        .fields
+                        .into_iter()
+                        .map(|(key, value)| (key, Value::String(value)))
+                        .collect();
+                    object.insert("fields".into(), Value::Object(fields));
+                }
+                serde_json::to_writer(&mut self.out, &object)?;
+                writeln!(self.out)?;
+            }
+            ExportFormat::Csv => {
+                let row = [
+                    timestamp,
+                    service.to_string(),
+                    record.container.to_string(),
+                    record.replica.to_string(),
+                    stream(record.stream).to_string(),
+                    level.unwrap_or_default(),
+                    structured.and_then(|s| s.msg).unwrap_or_default(),
+                    record.text().into_owned(),
+                ];
+                let row: Vec<String> = row.iter().map(|field| csv_field(field)).collect();
+                writeln!(self.out, "{}", row.join(","))?;
+            }
+            ExportFormat::Text => {
+                for line in record.text().split('\n') {
+                    writeln!(self.out, "{} {} | {}", timestamp, record.container, line)?;
+                }
+            }
+        }
+        self.count += 1;
+        Ok(())
+    }
+
+    /// Number of records written so far.
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    /// Flushes what's written and gives the writer back.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
+
+fn stream(stream: LogStream) -> &'static str {
+    match stream {
+        LogStream::Stdout => "stdout",
+        LogStream::Stderr => "stderr",
+        LogStream::Event => "event",
+    }
+}
+
+// Quotes a field of RFC 4180 CSV if it needs to be
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+    use std::sync::Arc;
+
+    fn record(raw: &str) -> LogRecord {
+        LogRecord {
+            seq: 0,
+            timestamp: Utc.timestamp_opt(1619863200, 0).unwrap(),
+            stream: LogStream::Stderr,
+            container: Arc::from("app_web_1"),
+            replica: 1,
+            raw: raw.to_string(),
+        }
+    }
+
+    fn export(format: ExportFormat, raws: &[&str]) -> String {
+        let mut exporter = Exporter::new(vec![], format).unwrap();
+        for raw in raws {
+            exporter.write("web", &record(raw), Format::Auto).unwrap();
+        }
+        assert_eq!(exporter.count(), raws.len());
+        String::from_utf8(exporter.finish().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_ndjson() {
+        let out = export(
+            ExportFormat::Ndjson,
+            &[r#"level=warn msg="disk full" free=3%"#, "plain"],
+        );
+        let lines: Vec<Value> = out
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines[0]["timestamp"], "2021-05-01T10:00:00Z");
+        assert_eq!(lines[0]["service"], "web");
+        assert_eq!(lines[0]["stream"], "stderr");
+        assert_eq!(lines[0]["format"], "logfmt");
+        assert_eq!(lines[0]["level"], "warn");
+        assert_eq!(lines[0]["msg"], "disk full");
+        assert_eq!(lines[0]["fields"]["free"], "3%");
+        assert_eq!(lines[1]["raw"], "plain");
+        assert!(lines[1].get("fields").is_none());
+    }
+
+    #[test]
+    fn test_csv() {
+        let out = export(ExportFormat::Csv, &["ERROR \"quoted\", comma\n  at main"]);
+        assert_eq!(
+            out,
+            format!(
+                "{}\n{}\n",
+                CSV_HEADER,
+                "2021-05-01T10:00:00Z,web,app_web_1,1,stderr,error,,\"ERROR \"\"quoted\"\", comma\n  at main\""
+            )
+        );
+    }
+
+    #[test]
+    fn test_text() {
+        let out = export(ExportFormat::Text, &["\x1b[31mboom\x1b[0m\n  at main"]);
+        assert_eq!(
+            out,
+            "2021-05-01T10:00:00Z app_web_1 | boom\n2021-05-01T10:00:00Z app_web_1 |   at main\n"
+        );
+        assert_eq!(
+            ExportFormat::from_path(Path::new("bug.csv")),
+            Some(ExportFormat::Csv)
+        );
+        assert_eq!("TEXT".parse(), Ok(ExportFormat::Text));
+    }
+}
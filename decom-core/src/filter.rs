@@ -0,0 +1,492 @@
+use crate::backend::LogStream;
+use crate::error::Error;
+use crate::parser::Format;
+use crate::record::{Level, LogRecord};
+use regex::Regex;
+use std::{cmp::Ordering, fmt, str::FromStr};
+
+/// An expression telling which records to show, such as
+/// `level>=warn and not msg~"healthcheck"`.
+///
+/// Comparisons are `field op value`:
+///
+/// - `level` with `=`, `!=`, `<`, `<=`, `>`, `>=` against `trace`, `debug`, `info`, `warn`,
+///   `error` or `fatal`. Lines without a level don't match any of them but `!=`.
+/// - `replica` with the same operators against a number.
+/// - `stream` with `=` or `!=` against `stdout` or `stderr`.
+/// - `msg` and `container` with `=` and `!=` against text, or `~` and `!~` against a regular
+///   expression. `msg` is the message of a structured line, and the whole text of others.
+///
+/// Values with spaces or operators are quoted with `"`. A value alone is short for `msg~value`
+/// taken literally, so `GET /api` shows lines with both words. Comparisons combine with `and`,
+/// `or`, `not` and parentheses. An empty expression shows every line. Lines decom inserts
+/// itself, like restart markers, are always shown.
+#[derive(Debug, Clone)]
+pub struct Filter {
+    source: String,
+    // `None` for an empty expression
+    expr: Option<Expr>,
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Level(Op, Level),
+    Replica(Op, u32),
+    Stream(LogStream),
+    Text(Field, Text),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Match,
+    NotMatch,
+}
+
+impl Op {
+    fn holds(self, ordering: Ordering) -> bool {
+        match self {
+            Op::Eq => ordering == Ordering::Equal,
+            Op::Ne => ordering != Ordering::Equal,
+            Op::Lt => ordering == Ordering::Less,
+            Op::Le => ordering != Ordering::Greater,
+            Op::Gt => ordering == Ordering::Greater,
+            Op::Ge => ordering != Ordering::Less,
+            Op::Match | Op::NotMatch => false,
+        }
+    }
+}
+
+impl fmt::Display for Op {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let symbol = match self {
+            Op::Eq => "=",
+            Op::Ne => "!=",
+            Op::Lt => "<",
+            Op::Le => "<=",
+            Op::Gt => ">",
+            Op::Ge => ">=",
+            Op::Match => "~",
+            Op::NotMatch => "!~",
+        };
+        write!(f, "{}", symbol)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Field {
+    Msg,
+    Container,
+}
+
+#[derive(Debug, Clone)]
+enum Text {
+    Equals(String),
+    Matches(Regex),
+}
+
+impl Filter {
+    pub fn parse(source: &str) -> Result<Filter, Error> {
+        let tokens = tokenize(source)?;
+        let expr = if tokens.is_empty() {
+            None
+        } else {
+            let mut parser = Parser { tokens, pos: 0 };
+            let expr = parser.or()?;
+            if let Some(token) = parser.peek() {
+                return Err(Error::InvalidFilter(format!("unexpected {}", token)));
+            }
+            Some(expr)
+        };
+        Ok(Filter {
+            source: source.trim().to_string(),
+            expr,
+        })
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.source
+    }
+
+    /// Whether the expression is empty, so that every record passes.
+    pub fn is_empty(&self) -> bool {
+        self.expr.is_none()
+    }
+
+    pub fn matches(&self, record: &LogRecord) -> bool {
+        self.matches_as(record, Format::Auto)
+    }
+
+    /// Whether the record passes, taking its line to be in the format when it comes to levels.
+    pub fn matches_as(&self, record: &LogRecord, format: Format) -> bool {
+        record.stream == LogStream::Event
+            || self
+                .expr
+                .as_ref()
+                .is_none_or(|expr| expr.eval(record, format))
+    }
+}
+
+impl FromStr for Filter {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Filter::parse(s)
+    }
+}
+
+impl fmt::Display for Filter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+impl Expr {
+    fn eval(&self, record: &LogRecord, format: Format) -> bool {
+        match self {
+            Expr::And(a, b) => a.eval(record, format) && b.eval(record, format),
+            Expr::Or(a, b) => a.eval(record, format) || b.eval(record, format),
+            Expr::Not(a) => !a.eval(record, format),
+            Expr::Level(op, level) => match record.level_as(format) {
+                Some(actual) => op.holds(actual.cmp(level)),
+                None => *op == Op::Ne,
+            },
+            Expr::Replica(op, n) => op.holds(record.replica.cmp(n)),
+            Expr::Stream(stream) => record.stream == *stream,
+            Expr::Text(field, text) => {
+                let msg = match field {
+                    Field::Msg => record
+                        .parse_as(format)
+                        .and_then(|structured| structured.msg),
+                    Field::Container => None,
+                };
+                let raw = record.text();
+                let value = match field {
+                    Field::Msg => msg.as_deref().unwrap_or(&raw),
+                    Field::Container => &record.container,
+                };
+                match text {
+                    Text::Equals(s) => value == s,
+                    Text::Matches(regex) => regex.is_match(value),
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Open,
+    Close,
+    Op(Op),
+    Word(String),
+    Quoted(String),
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Open => write!(f, "`(`"),
+            Token::Close => write!(f, "`)`"),
+            Token::Op(op) => write!(f, "`{}`", op),
+            Token::Word(word) => write!(f, "`{}`", word),
+            Token::Quoted(text) => write!(f, "\"{}\"", text),
+        }
+    }
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>, Error> {
+    let mut tokens = vec![];
+    let mut chars = source.chars().peekable();
+    while let Some(c) = chars.next() {
+        let token = match c {
+            c if c.is_whitespace() => continue,
+            '(' => Token::Open,
+            ')' => Token::Close,
+            '"' => {
+                let mut text = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c) => text.push(c),
+                            None => break,
+                        },
+                        Some(c) => text.push(c),
+                        None => {
+                            return Err(Error::InvalidFilter("unterminated string".to_string()))
+                        }
+                    }
+                }
+                Token::Quoted(text)
+            }
+            '=' => {
+                chars.next_if_eq(&'=');
+                Token::Op(Op::Eq)
+            }
+            '~' => Token::Op(Op::Match),
+            '!' if chars.next_if_eq(&'=').is_some() => Token::Op(Op::Ne),
+            '!' if chars.next_if_eq(&'~').is_some() => Token::Op(Op::NotMatch),
+            '<' if chars.next_if_eq(&'=').is_some() => Token::Op(Op::Le),
+            '<' => Token::Op(Op::Lt),
+            '>' if chars.next_if_eq(&'=').is_some() => Token::Op(Op::Ge),
+            '>' => Token::Op(Op::Gt),
+            '!' => {
+                return Err(Error::InvalidFilter(
+                    "`!` must be followed by `=` or `~`, use `not` to negate".to_string(),
+                ))
+            }
+            c => {
+                let mut word = c.to_string();
+                while let Some(c) =
+                    chars.next_if(|c| !c.is_whitespace() && !"()\"=~!<>".contains(*c))
+                {
+                    word.push(c);
+                }
+                Token::Word(word)
+            }
+        };
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn keyword(&mut self, keyword: &str) -> bool {
+        match self.peek() {
+            Some(Token::Word(word)) if word.eq_ignore_ascii_case(keyword) => {
+                self.pos += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn operand_follows(&self) -> bool {
+        match self.peek() {
+            Some(Token::Open) | Some(Token::Quoted(_)) => true,
+            Some(Token::Word(word)) => !word.eq_ignore_ascii_case("or"),
+            _ => false,
+        }
+    }
+
+    fn or(&mut self) -> Result<Expr, Error> {
+        let mut expr = self.and()?;
+        while self.keyword("or") {
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, Error> {
+        let mut expr = self.not()?;
+        // Operands next to each other have to hold both
+        while self.keyword("and") || self.operand_follows() {
+            expr = Expr::And(Box::new(expr), Box::new(self.not()?));
+        }
+        Ok(expr)
+    }
+
+    fn not(&mut self) -> Result<Expr, Error> {
+        if self.keyword("not") {
+            return Ok(Expr::Not(Box::new(self.not()?)));
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<Expr, Error> {
+        match self.next() {
+            Some(Token::Open) => {
+                let expr = self.or()?;
+                match self.next() {
+                    Some(Token::Close) => Ok(expr),
+                    _ => Err(Error::InvalidFilter("missing `)`".to_string())),
+                }
+            }
+            Some(Token::Word(field)) if matches!(self.peek(), Some(Token::Op(_))) => {
+                let op = match self.next() {
+                    Some(Token::Op(op)) => op,
+                    _ => unreachable!(),
+                };
+                let value = match self.next() {
+                    Some(Token::Word(value)) | Some(Token::Quoted(value)) => value,
+                    _ => {
+                        return Err(Error::InvalidFilter(format!(
+                            "missing value to compare `{}` with",
+                            field
+                        )))
+                    }
+                };
+                comparison(&field, op, &value)
+            }
+            Some(Token::Word(text)) | Some(Token::Quoted(text)) => Ok(Expr::Text(
+                Field::Msg,
+                Text::Matches(Regex::new(&regex::escape(&text))?),
+            )),
+            Some(token) => Err(Error::InvalidFilter(format!("unexpected {}", token))),
+            None => Err(Error::InvalidFilter("unexpected end".to_string())),
+        }
+    }
+}
+
+fn comparison(field: &str, op: Op, value: &str) -> Result<Expr, Error> {
+    let invalid = || Error::InvalidFilter(format!("`{}` can't be compared with `{}`", field, op));
+    let expr = match field.to_ascii_lowercase().as_str() {
+        "level" => {
+            let level = value
+                .parse()
+                .map_err(|_| Error::InvalidFilter(format!("unknown level `{}`", value)))?;
+            match op {
+                Op::Match | Op::NotMatch => return Err(invalid()),
+                op => Expr::Level(op, level),
+            }
+        }
+        "replica" => {
+            let n = value
+                .parse()
+                .map_err(|_| Error::InvalidFilter(format!("invalid replica `{}`", value)))?;
+            match op {
+                Op::Match | Op::NotMatch => return Err(invalid()),
+                op => Expr::Replica(op, n),
+            }
+        }
+        "stream" => {
+            let stream = match value.to_ascii_lowercase().as_str() {
+                "stdout" => LogStream::Stdout,
+                "stderr" => LogStream::Stderr,
+                _ => return Err(Error::InvalidFilter(format!("unknown stream `{}`", value))),
+            };
+            match op {
+                Op::Eq => Expr::Stream(stream),
+                Op::Ne => Expr::Not(Box::new(Expr::Stream(stream))),
+                _ => return Err(invalid()),
+            }
+        }
+        name @ "msg" | name @ "container" => {
+            let field = if name == "msg" {
+                Field::Msg
+            } else {
+                Field::Container
+            };
+            match op {
+                Op::Eq => Expr::Text(field, Text::Equals(value.to_string())),
+                Op::Ne => Expr::Not(Box::new(Expr::Text(field, Text::Equals(value.to_string())))),
+                Op::Match => Expr::Text(field, Text::Matches(Regex::new(value)?)),
+                Op::NotMatch => Expr::Not(Box::new(Expr::Text(
+                    field,
+                    Text::Matches(Regex::new(value)?),
+                ))),
+                _ => return Err(invalid()),
+            }
+        }
+        _ => return Err(Error::InvalidFilter(format!("unknown field `{}`", field))),
+    };
+    Ok(expr)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use std::sync::Arc;
+
+    fn record(stream: LogStream, replica: u32, raw: &str) -> LogRecord {
+        LogRecord {
+            seq: 0,
+            timestamp: Utc::now(),
+            stream,
+            container: Arc::from(format!("app_web_{}", replica).as_str()),
+            replica,
+            raw: raw.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_matches() {
+        let filter = Filter::parse(r#"level>=warn and not msg~"healthcheck""#).unwrap();
+        assert!(filter.matches(&record(LogStream::Stdout, 1, "ERROR db is down")));
+        assert!(!filter.matches(&record(LogStream::Stdout, 1, "INFO started")));
+        assert!(!filter.matches(&record(LogStream::Stdout, 1, "WARN healthcheck slow")));
+        assert!(!filter.matches(&record(LogStream::Stdout, 1, "no level")));
+        assert!(filter.matches(&record(LogStream::Event, 1, "--- container restarted ---")));
+
+        let filter =
+            Filter::parse("(stream=stderr or replica > 1) and container != app_web_3").unwrap();
+        assert!(filter.matches(&record(LogStream::Stderr, 1, "")));
+        assert!(filter.matches(&record(LogStream::Stdout, 2, "")));
+        assert!(!filter.matches(&record(LogStream::Stdout, 1, "")));
+        assert!(!filter.matches(&record(LogStream::Stderr, 3, "")));
+
+        // A bare value looks for the text as it is
+        let filter = Filter::parse("GET /api.v1").unwrap();
+        assert!(filter.matches(&record(LogStream::Stdout, 1, "GET /api.v1/users")));
+        assert!(!filter.matches(&record(LogStream::Stdout, 1, "GET /apixv1/users")));
+        assert!(!filter.matches(&record(LogStream::Stdout, 1, "POST /api.v1/users")));
+        assert_eq!(filter.to_string(), "GET /api.v1");
+
+        // Levels come from the service's format
+        let filter = Filter::parse("level>=warn").unwrap();
+        let line = record(LogStream::Stdout, 1, r#"at=ERROR msg="done""#);
+        assert!(filter.matches_as(&line, Format::Plain));
+        assert!(!filter.matches_as(&line, Format::Logfmt));
+        assert!(!filter.matches_as(&line, Format::Raw));
+
+        // Only the message of a structured line
+        let line = record(
+            LogStream::Stdout,
+            1,
+            r#"{"level":"info","msg":"GET /health","path":"/api"}"#,
+        );
+        assert!(Filter::parse("health").unwrap().matches(&line));
+        assert!(!Filter::parse("api").unwrap().matches(&line));
+        assert!(Filter::parse("msg=\"GET /health\"").unwrap().matches(&line));
+        assert!(Filter::parse("api").unwrap().matches_as(&line, Format::Raw));
+
+        // An empty expression lets everything through
+        let filter = Filter::parse(" ").unwrap();
+        assert!(filter.is_empty());
+        assert!(filter.matches(&record(LogStream::Stdout, 1, "INFO started")));
+        assert_eq!(filter.to_string(), "");
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let error = |source: &str| Filter::parse(source).unwrap_err().to_string();
+        assert_eq!(error("level>=loud"), "invalid filter: unknown level `loud`");
+        assert_eq!(error("size>1"), "invalid filter: unknown field `size`");
+        assert_eq!(error("(stream=stdout"), "invalid filter: missing `)`");
+        assert_eq!(error("msg=\"open"), "invalid filter: unterminated string");
+        assert_eq!(
+            error("level~warn"),
+            "invalid filter: `level` can't be compared with `~`"
+        );
+        assert!(matches!(
+            Filter::parse(r#"msg~"(""#).unwrap_err(),
+            Error::InvalidPattern(_)
+        ));
+        assert!(Filter::parse("a and").is_err());
+    }
+}
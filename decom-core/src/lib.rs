@@ -1,7 +1,35 @@
+pub mod ansi;
+pub mod backend;
+pub mod discovery;
 pub mod docker;
+pub mod docker_api;
 pub mod docker_compose;
+mod error;
+mod export;
+mod filter;
 mod log_collector;
+mod multiline;
+pub mod parser;
+pub mod project;
+mod record;
+pub mod recording;
+mod search;
+pub mod store;
+pub mod timeline;
 
+pub use backend::{Backend, ContainerFilter, LogLine, LogStream, LogsOptions};
+pub use discovery::{Discovery, ServiceEvent};
 pub use docker::Container;
-pub use docker_compose::Service;
-pub use log_collector::LogCollector;
+pub use docker_compose::{Replica, Service};
+pub use error::{Error, Result};
+pub use export::{ExportFormat, Exporter};
+pub use filter::Filter;
+pub use log_collector::{Backlog, LogCollector};
+pub use multiline::Multiline;
+pub use parser::{Format, Structured};
+pub use project::{Project, ProjectOptions};
+pub use record::{split_timestamp, Level, LogRecord};
+pub use recording::{Recorder, Recording, Replay};
+pub use search::{Query, QueryOptions, Search};
+pub use store::{LogStore, Retention, RingBuffer, SegmentedStore};
+pub use timeline::Timeline;
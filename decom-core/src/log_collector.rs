@@ -1,90 +1,513 @@
-use crossbeam::channel::{self, Receiver, Sender};
-use futures::prelude::*;
+use crate::backend::{Backend, LogStream, LogsOptions};
+use crate::docker_compose::{Replica, Service};
+use crate::error::Error;
+use crate::multiline::{Joiner, Multiline};
+use crate::parser::Format;
+use crate::record::LogRecord;
+use crate::search::{Query, Search};
+use crate::store::{LogStore, RingBuffer};
+use chrono::{DateTime, Utc};
+use futures::{future::BoxFuture, prelude::*};
+use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
-use std::{error::Error, process::Stdio, result::Result, task::Poll, thread};
-use tokio::io::{AsyncBufReadExt, BufReader};
-use tokio::{process::Command, runtime::Runtime};
+use std::{ops::Range, pin::Pin, result::Result, task::Poll, time::Duration};
+use tokio::{sync::Notify, task::JoinHandle};
 
+// How long a record is held for lines continuing it before it's stored
+const JOIN_WAIT: Duration = Duration::from_millis(200);
+
+/// How much of the log written before attaching is collected.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Backlog {
+    /// Only lines written at or after this time.
+    pub since: Option<DateTime<Utc>>,
+    /// Only lines written before this time. Containers aren't followed when it's set.
+    pub until: Option<DateTime<Utc>>,
+    /// Only this many of the latest lines of each replica.
+    pub tail: Option<usize>,
+}
+
+// What has been loaded of a replica's log from before attaching
+#[derive(Debug, Default)]
+struct History {
+    oldest: Option<DateTime<Utc>>,
+    // Lines of the records stored at the oldest time, which a fetch gets again
+    at_oldest: Vec<String>,
+    complete: bool,
+    fetching: bool,
+}
+
+// Where followers put records, shared with the collector
+#[derive(Clone)]
+struct Sink {
+    logs: Arc<Mutex<Box<dyn LogStore>>>,
+    notify: Arc<Notify>,
+    history: Arc<Mutex<HashMap<u32, History>>>,
+}
+
+impl Sink {
+    fn push(&self, record: LogRecord) -> Result<(), Error> {
+        if record.stream != LogStream::Event {
+            let mut history = self.history.lock().expect("failed to lock");
+            let history = history.entry(record.replica).or_default();
+            if history
+                .oldest
+                .is_none_or(|oldest| record.timestamp < oldest)
+            {
+                history.oldest = Some(record.timestamp);
+                history.at_oldest.clear();
+            }
+            if history.oldest == Some(record.timestamp) {
+                let lines = record.raw.split('\n').map(String::from);
+                history.at_oldest.extend(lines);
+            }
+        }
+        self.logs.lock().expect("failed to lock").push(record)?;
+        self.notify.notify_one();
+        Ok(())
+    }
+}
+
+/// Follows logs of every replica of a service and keeps them in a `LogStore`.
+///
+/// Followers run as tasks on the Tokio runtime `start` is called on, and are aborted when the
+/// collector is dropped.
 pub struct LogCollector {
+    backend: Arc<dyn Backend>,
     service_name: String,
-    container_name: String,
+    replicas: Vec<Replica>,
+    format: Format,
+    multiline: Multiline,
     marker: usize,
-    notifier: (Sender<usize>, Receiver<usize>),
-    transfer: (Sender<String>, Receiver<String>),
-    logs: Arc<Mutex<Vec<String>>>,
+    notified: Option<BoxFuture<'static, ()>>,
+    backlog: Backlog,
+    started: bool,
+    // Follower and history fetches of each replica, by replica number
+    tasks: Vec<(u32, JoinHandle<()>)>,
+    // How often to look for the container again once its logs end
+    retry: Duration,
+    sink: Sink,
 }
 
 impl LogCollector {
-    pub fn new(service_name: &str, container_name: &str) -> Self {
+    /// Holds records in memory, as much of them as the service's retention allows.
+    pub fn new(backend: Arc<dyn Backend>, service: &Service) -> Self {
+        let store = RingBuffer::new(service.retention);
+        LogCollector::with_store(backend, service, Box::new(store))
+    }
+
+    pub fn with_store(
+        backend: Arc<dyn Backend>,
+        service: &Service,
+        store: Box<dyn LogStore>,
+    ) -> Self {
         LogCollector {
-            service_name: service_name.to_string(),
-            container_name: container_name.to_string(),
+            backend,
+            service_name: service.service_name.clone(),
+            replicas: service.replicas.clone(),
+            format: service.format,
+            multiline: service.multiline.clone(),
             marker: 0,
-            notifier: channel::unbounded(),
-            transfer: channel::unbounded(),
-            logs: Arc::new(Mutex::new(vec![])),
+            notified: None,
+            backlog: Backlog::default(),
+            started: false,
+            tasks: vec![],
+            retry: Duration::from_secs(1),
+            sink: Sink {
+                logs: Arc::new(Mutex::new(store)),
+                notify: Arc::new(Notify::new()),
+                history: Arc::new(Mutex::new(HashMap::new())),
+            },
         }
     }
 
+    /// Limits what is collected from before `start`.
+    pub fn with_backlog(mut self, backlog: Backlog) -> Self {
+        self.backlog = backlog;
+        self
+    }
+
+    /// Takes log lines to be in the format, whatever the service's label says.
+    pub fn with_format(mut self, format: Format) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Joins lines into records as told, whatever the service's label says. Must be called
+    /// before `start`.
+    pub fn with_multiline(mut self, multiline: Multiline) -> Self {
+        self.multiline = multiline;
+        self
+    }
+
+    /// Spawns a task per replica. Must be called within a Tokio runtime.
     pub fn start(&mut self) {
-        // Main: command runner
-        let name = self.container_name.clone();
-        let transfer = self.transfer.0.clone();
-        thread::spawn(move || {
-            // println!("collector: logs: spawn");
-            let rt = Runtime::new().unwrap();
-            rt.block_on(async {
-                // println!("collector: logs: block_on");
-                let _ = LogCollector::logs(name, transfer).await;
-            });
-        });
+        self.started = true;
+        for replica in self.replicas.clone() {
+            self.follow(replica);
+        }
+    }
 
-        // Sub: storing logs
-        let logs = self.logs.clone();
-        let notifier = self.notifier.0.clone();
-        let transfer = self.transfer.1.clone();
-        thread::spawn(move || {
-            // println!("collector: collector: spawn");
-            while let Ok(line) = transfer.recv() {
-                let mut logs = logs.lock().expect("failed to lock");
-                // println!("collector: collector: recv");
-                logs.push(line);
-                let _ = notifier.send(logs.len());
+    /// Follows replicas added to the service since and stops following removed ones.
+    pub fn update(&mut self, service: &Service) {
+        self.tasks.retain(|(number, task)| {
+            let keep = service.replicas.iter().any(|r| r.number == *number);
+            if !keep {
+                task.abort();
             }
+            keep
         });
+        if self.started {
+            let added: Vec<Replica> = service
+                .replicas
+                .iter()
+                .filter(|r| !self.replicas.iter().any(|known| known.number == r.number))
+                .cloned()
+                .collect();
+            for replica in added {
+                self.follow(replica);
+            }
+        }
+        self.replicas = service.replicas.clone();
+    }
+
+    fn follow(&mut self, replica: Replica) {
+        // Without limits, the whole log is loaded on the first attach
+        let complete = self.backlog.since.is_none() && self.backlog.tail.is_none();
+        self.sink
+            .history
+            .lock()
+            .expect("failed to lock")
+            .entry(replica.number)
+            .or_default()
+            .complete = complete;
+
+        let number = replica.number;
+        let follower = LogCollector::logs(
+            self.backend.clone(),
+            replica,
+            self.sink.clone(),
+            Joiner::new(self.multiline.clone()),
+            self.backlog,
+            self.retry,
+        );
+        self.tasks.push((
+            number,
+            tokio::spawn(async move {
+                let _ = follower.await;
+            }),
+        ));
     }
 
-    // TODO: No need to use async with tokio's Command. Use Command in standard library.
-    async fn logs(container_name: String, transfer: Sender<String>) -> Result<(), Box<dyn Error>> {
-        let mut child = Command::new("docker")
-            .args(&["logs", "-f", container_name.as_str()])
-            .stdout(Stdio::piped())
-            .spawn()?;
-        let stdout = child.stdout.take().expect("failed to get child output");
-        let mut reader = BufReader::new(stdout).lines();
+    /// Loads up to `lines` more lines of each replica from before the oldest ones collected.
+    ///
+    /// They are stored after newer records, so show records ordered by timestamp, e.g. with a
+    /// `Timeline`, rather than by sequence number. A fetch which fails stores nothing but a
+    /// marker telling the error, and may be tried again.
+    pub fn fetch_more(&mut self, lines: usize) {
+        // Earlier fetches which are done
+        self.tasks.retain(|(_, task)| !task.is_finished());
+        for replica in self.replicas.clone() {
+            let (until, mut stored) = {
+                let mut history = self.sink.history.lock().expect("failed to lock");
+                let history = history.entry(replica.number).or_default();
+                match history.oldest {
+                    Some(oldest) if !history.complete && !history.fetching => {
+                        history.fetching = true;
+                        (oldest, history.at_oldest.clone())
+                    }
+                    _ => continue,
+                }
+            };
+
+            let backend = self.backend.clone();
+            let sink = self.sink.clone();
+            let since = self.backlog.since;
+            let number = replica.number;
+            let mut joiner = Joiner::new(self.multiline.clone());
+            let fetch = async move {
+                let options = LogsOptions {
+                    follow: false,
+                    timestamps: true,
+                    since,
+                    until: Some(until),
+                    // `until` includes the lines already stored at the very time of the oldest one
+                    tail: Some(lines + stored.len()),
+                };
+                let container: Arc<str> = replica.container_name.as_str().into();
+                let mut older = backend.logs(&replica.container_name, &options);
+                let mut count = 0;
+                // Held until the whole fetch went through, as storing part of it would leave a
+                // gap between it and the newer lines
+                let mut records = vec![];
+                let error = loop {
+                    let line = match older.next().await {
+                        Some(Ok(line)) => line,
+                        Some(Err(e)) => break Some(e),
+                        None => break None,
+                    };
+                    let timestamp = line.timestamp.unwrap_or_else(Utc::now);
+                    if timestamp > until {
+                        continue;
+                    }
+                    if timestamp == until {
+                        if let Some(i) = stored.iter().position(|text| *text == line.text) {
+                            stored.swap_remove(i);
+                            continue;
+                        }
+                    }
+                    count += 1;
+                    records.extend(joiner.push(LogRecord {
+                        seq: 0,
+                        timestamp,
+                        stream: line.stream,
+                        container: container.clone(),
+                        replica: replica.number,
+                        raw: line.text,
+                    }));
+                };
+                let result = match &error {
+                    None => records
+                        .into_iter()
+                        .chain(joiner.flush())
+                        .try_for_each(|record| sink.push(record)),
+                    Some(e) => sink.push(LogRecord {
+                        seq: 0,
+                        timestamp: Utc::now(),
+                        stream: LogStream::Event,
+                        container: container.clone(),
+                        replica: replica.number,
+                        raw: format!("--- error fetching older logs: {} ---", e),
+                    }),
+                };
 
-        while let Some(line) = reader.next_line().await? {
-            // println!("collector: {}", line);
-            let _ = transfer.send(line);
+                let mut history = sink.history.lock().expect("failed to lock");
+                let history = history.entry(replica.number).or_default();
+                history.fetching = false;
+                // A failed fetch may be tried again
+                history.complete = error.is_none() && count < lines;
+                result
+            };
+            self.tasks.push((
+                number,
+                tokio::spawn(async move {
+                    let _ = fetch.await;
+                }),
+            ));
         }
+    }
 
-        Ok(())
+    /// Whether every follower and fetch has ended, which only happens for a backlog with an
+    /// `until`, once the logs up to then have been stored.
+    pub fn is_finished(&self) -> bool {
+        self.started && self.tasks.iter().all(|(_, task)| task.is_finished())
+    }
+
+    /// Whether everything from before attaching has been loaded, within the backlog's `since`.
+    pub fn is_complete(&self) -> bool {
+        let history = self.sink.history.lock().expect("failed to lock");
+        self.replicas
+            .iter()
+            .all(|r| history.get(&r.number).is_none_or(|h| h.complete))
     }
 
+    /// Follows a replica for as long as the collector lives.
+    ///
+    /// When the log stream ends, the container has stopped or been removed. The follower then
+    /// waits for a running container of the same name, which may be a recreated one with a new
+    /// ID, and resumes from the last line seen with a marker telling what happened.
+    async fn logs(
+        backend: Arc<dyn Backend>,
+        replica: Replica,
+        sink: Sink,
+        mut joiner: Joiner,
+        backlog: Backlog,
+        retry: Duration,
+    ) -> Result<(), Error> {
+        let container: Arc<str> = replica.container_name.as_str().into();
+        let record = |stream, timestamp, raw| LogRecord {
+            seq: 0,
+            timestamp,
+            stream,
+            container: container.clone(),
+            replica: replica.number,
+            raw,
+        };
+
+        let mut attached = backend.inspect(&replica.container_name).await.ok();
+        // Time of the newest line seen, and the lines seen at that time
+        let mut last: Option<DateTime<Utc>> = None;
+        let mut at_last: Vec<String> = vec![];
+        // Whether an error was stored and no line came since, so that it's only stored once
+        let mut failing = false;
+        loop {
+            // The backlog only limits the first attach, later ones resume after the last line.
+            let options = LogsOptions {
+                follow: backlog.until.is_none(),
+                timestamps: true,
+                since: last.or(backlog.since),
+                until: backlog.until,
+                tail: if last.is_none() { backlog.tail } else { None },
+            };
+            let mut lines = backend.logs(&replica.container_name, &options);
+            let mut error = None;
+            // `since` is inclusive, so the lines stored at the last time are sent again
+            let mut resent = last.map(|last| (last, at_last.clone()));
+
+            loop {
+                // A held record is stored once no line continues it for a while
+                let next = if joiner.is_pending() {
+                    match tokio::time::timeout(JOIN_WAIT, lines.next()).await {
+                        Ok(next) => next,
+                        Err(_) => {
+                            if let Some(pending) = joiner.flush() {
+                                sink.push(pending)?;
+                            }
+                            continue;
+                        }
+                    }
+                } else {
+                    lines.next().await
+                };
+                let line = match next {
+                    Some(Ok(line)) => line,
+                    // The container went away in the middle of streaming
+                    Some(Err(Error::NoSuchContainer(_))) | None => break,
+                    Some(Err(e)) => {
+                        error = Some(e);
+                        break;
+                    }
+                };
+                let timestamp = line.timestamp.unwrap_or_else(Utc::now);
+                if let Some((since, stored)) = &mut resent {
+                    match line.timestamp {
+                        Some(t) if t <= *since => {
+                            if let Some(i) = stored.iter().position(|text| *text == line.text) {
+                                stored.swap_remove(i);
+                                continue;
+                            }
+                        }
+                        // Lines are new from then on
+                        _ => resent = None,
+                    }
+                }
+                // Stdout and stderr aren't read in order, so a line may be older than the last
+                if last.is_none_or(|last| timestamp > last) {
+                    last = Some(timestamp);
+                    at_last.clear();
+                }
+                if last == Some(timestamp) {
+                    at_last.push(line.text.clone());
+                }
+                failing = false;
+                if let Some(done) = joiner.push(record(line.stream, timestamp, line.text)) {
+                    sink.push(done)?;
+                }
+            }
+            if let Some(pending) = joiner.flush() {
+                sink.push(pending)?;
+            }
+            if let Some(e) = error {
+                if !failing {
+                    failing = true;
+                    sink.push(record(
+                        LogStream::Event,
+                        Utc::now(),
+                        format!("--- error: {} ---", e),
+                    ))?;
+                }
+                // Retrying won't help until the user does something about these
+                if let Error::CommandNotFound(_) | Error::PermissionDenied(_) = e {
+                    return Err(e);
+                }
+            }
+            if backlog.until.is_some() {
+                return Ok(());
+            }
+
+            let details = loop {
+                tokio::time::sleep(retry).await;
+                match backend.inspect(&replica.container_name).await {
+                    Ok(details) if details.state.running => break details,
+                    _ => {}
+                }
+            };
+            let event = match &attached {
+                Some(prev) if prev.id != details.id => Some("container recreated"),
+                Some(prev) if prev.state.started_at != details.state.started_at => {
+                    Some("container restarted")
+                }
+                Some(_) => None,
+                None => Some("container started"),
+            };
+            if let Some(event) = event {
+                let timestamp = DateTime::parse_from_rfc3339(&details.state.started_at)
+                    .map(|t| t.with_timezone(&Utc))
+                    .unwrap_or_else(|_| Utc::now());
+                sink.push(record(
+                    LogStream::Event,
+                    timestamp,
+                    format!("--- {} ---", event),
+                ))?;
+            }
+            attached = Some(details);
+        }
+    }
+
+    /// Number of records ever stored, including evicted ones.
     fn len(&self) -> usize {
-        let logs = self.logs.lock().expect("failed to lock");
-        logs.len()
+        let logs = self.sink.logs.lock().expect("failed to lock");
+        logs.next_seq() as usize
+    }
+
+    pub fn service_name(&self) -> &str {
+        &self.service_name
+    }
+
+    pub fn replicas(&self) -> &[Replica] {
+        &self.replicas
+    }
+
+    /// Format to parse the log lines in.
+    pub fn format(&self) -> Format {
+        self.format
+    }
+
+    /// Sequence number of the oldest record still held, older ones have been evicted.
+    pub fn first_seq(&self) -> u64 {
+        let logs = self.sink.logs.lock().expect("failed to lock");
+        logs.first_seq()
+    }
+
+    pub fn get(&self, seq: u64) -> Option<LogRecord> {
+        let logs = self.sink.logs.lock().expect("failed to lock");
+        logs.get(seq)
+    }
+
+    /// Returns records stored at or after the given sequence number.
+    pub fn since(&self, seq: u64) -> Vec<LogRecord> {
+        let logs = self.sink.logs.lock().expect("failed to lock");
+        let next = logs.next_seq();
+        logs.range(seq..next)
+    }
+
+    /// Starts searching records of the collector, including ones stored later.
+    pub fn search(&self, query: Query) -> Search {
+        Search::start(self.sink.logs.clone(), query)
     }
 
-    // TODO: Don't copy string, return reference
-    pub fn slice(&self) -> Vec<String> {
-        let logs = self.logs.lock().expect("failed to lock");
-        logs.iter()
-            .rev()
-            .take(10)
-            .map(|line| line.clone())
-            .rev()
-            .collect()
+    /// Returns records within the range of sequence numbers, skipping evicted ones.
+    pub fn slice(&self, range: Range<u64>) -> Vec<LogRecord> {
+        let logs = self.sink.logs.lock().expect("failed to lock");
+        logs.range(range)
+    }
+}
+
+impl Drop for LogCollector {
+    fn drop(&mut self) {
+        for (_, task) in &self.tasks {
+            task.abort();
+        }
     }
 }
 
@@ -92,32 +515,507 @@ impl Stream for LogCollector {
     type Item = (String, usize, usize);
 
     fn poll_next(
-        mut self: std::pin::Pin<&mut Self>,
+        mut self: Pin<&mut Self>,
         cx: &mut std::task::Context<'_>,
-    ) -> std::task::Poll<Option<Self::Item>> {
-        // println!("collector: poll_next: enter");
-        let count = self.len();
-        let diff = count - self.marker;
-        if diff == 0 {
-            let receiver = self.notifier.1.clone();
-            let waker = cx.waker().clone();
-            thread::spawn(move || {
-                if let Ok(_count) = receiver.recv() {
-                    // println!("collector: poll_next: recv lines={}", count);
-                } else {
-                    // println!("collector: poll_next: failed");
-                }
-                // println!("collector: poll_next: wake");
-                waker.wake();
-            });
+    ) -> Poll<Option<Self::Item>> {
+        loop {
+            let count = self.len();
+            let diff = count - self.marker;
+            if diff > 0 {
+                self.marker = count;
+                return Poll::Ready(Some((self.service_name.clone(), count, diff)));
+            }
+
+            // A notification sent before waiting is kept as a permit, so none is missed
+            let notify = self.sink.notify.clone();
+            let notified = self
+                .notified
+                .get_or_insert_with(|| async move { notify.notified().await }.boxed());
+            match notified.as_mut().poll(cx) {
+                Poll::Ready(()) => self.notified = None,
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::{ContainerFilter, LogLine};
+    use crate::docker::{Container, ContainerDetails};
+    use crate::store::Retention;
+    use chrono::TimeZone;
+    use futures::stream::BoxStream;
+
+    struct Fake;
+
+    impl Backend for Fake {
+        fn containers(
+            &self,
+            _filter: &ContainerFilter,
+        ) -> BoxFuture<'_, Result<Vec<Container>, Error>> {
+            future::ready(Ok(vec![])).boxed()
+        }
+
+        fn inspect(&self, id: &str) -> BoxFuture<'_, Result<ContainerDetails, Error>> {
+            future::ready(Err(Error::NoSuchContainer(id.into()))).boxed()
+        }
+
+        fn logs(
+            &self,
+            id: &str,
+            _options: &LogsOptions,
+        ) -> BoxStream<'static, Result<LogLine, Error>> {
+            let lines: Vec<Result<LogLine, Error>> = (0..3)
+                .map(|i| Ok(LogLine::new(LogStream::Stdout, format!("{} {}", id, i))))
+                .collect();
+            stream::iter(lines).boxed()
+        }
+    }
+
+    fn service() -> Service {
+        Service {
+            service_name: "web".into(),
+            replicas: (1..=2)
+                .map(|number| Replica {
+                    number,
+                    container_name: format!("app_web_{}", number),
+                    container_id: number.to_string(),
+                    state: "running".into(),
+                })
+                .collect(),
+            format: Format::Auto,
+            multiline: Multiline::Auto,
+            retention: Retention::default(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_collect() {
+        let mut collector = LogCollector::new(Arc::new(Fake), &service());
+        collector.start();
+
+        let mut total = 0;
+        while total < 6 {
+            let (name, count, _) = collector.next().await.unwrap();
+            assert_eq!(name, "web");
+            total = count;
+        }
+        assert_eq!(total, 6);
+
+        let records = collector.slice(0..6);
+        let raws: Vec<&str> = records
+            .iter()
+            .filter(|r| r.replica == 2)
+            .map(|r| r.raw.as_str())
+            .collect();
+        assert_eq!(raws, vec!["app_web_2 0", "app_web_2 1", "app_web_2 2"]);
+        // Containers are followed until the collector is dropped
+        assert!(!collector.is_finished());
+
+        // Unless the backlog ends
+        let backlog = Backlog {
+            until: Some(Utc::now()),
+            ..Backlog::default()
+        };
+        let mut collector = LogCollector::new(Arc::new(Fake), &service()).with_backlog(backlog);
+        collector.start();
+        while !collector.is_finished() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(collector.slice(0..10).len(), 6);
+    }
+
+    // Container which writes a stack trace and then nothing more
+    struct Panicking;
+
+    impl Backend for Panicking {
+        fn containers(
+            &self,
+            _filter: &ContainerFilter,
+        ) -> BoxFuture<'_, Result<Vec<Container>, Error>> {
+            future::ready(Ok(vec![])).boxed()
+        }
+
+        fn inspect(&self, id: &str) -> BoxFuture<'_, Result<ContainerDetails, Error>> {
+            future::ready(Err(Error::NoSuchContainer(id.into()))).boxed()
+        }
+
+        fn logs(
+            &self,
+            _id: &str,
+            _options: &LogsOptions,
+        ) -> BoxStream<'static, Result<LogLine, Error>> {
+            let lines: Vec<Result<LogLine, Error>> = [
+                "starting",
+                "java.lang.Error: boom",
+                "\tat App.main(App.java:3)",
+            ]
+            .iter()
+            .map(|text| Ok(LogLine::new(LogStream::Stderr, text.to_string())))
+            .collect();
+            stream::iter(lines).chain(stream::pending()).boxed()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_multiline() {
+        let mut service = service();
+        service.replicas.truncate(1);
+        let mut collector = LogCollector::new(Arc::new(Panicking), &service);
+        collector.start();
+
+        // The stack trace is stored once no line has continued it for a while
+        let mut total = 0;
+        while total < 2 {
+            total = collector.next().await.unwrap().1;
+        }
+        let raws: Vec<String> = collector.slice(0..2).into_iter().map(|r| r.raw).collect();
+        assert_eq!(
+            raws,
+            [
+                "starting",
+                "java.lang.Error: boom\n\tat App.main(App.java:3)"
+            ]
+        );
+    }
+
+    // Container which is restarted once its first logs have been read
+    struct Restarting {
+        calls: Mutex<Vec<Option<DateTime<Utc>>>>,
+    }
+
+    fn at(secs: i64) -> DateTime<Utc> {
+        Utc.timestamp_opt(secs, 0).unwrap()
+    }
 
-            // println!("collector: poll_next: pending");
-            Poll::Pending
-        } else {
-            self.marker = count;
+    impl Backend for Restarting {
+        fn containers(
+            &self,
+            _filter: &ContainerFilter,
+        ) -> BoxFuture<'_, Result<Vec<Container>, Error>> {
+            future::ready(Ok(vec![])).boxed()
+        }
+
+        fn inspect(&self, _id: &str) -> BoxFuture<'_, Result<ContainerDetails, Error>> {
+            let started = if self.calls.lock().unwrap().is_empty() {
+                "2021-05-01T10:00:00Z"
+            } else {
+                "2021-05-01T10:00:05Z"
+            };
+            let json = format!(
+                r#"{{"Id":"abc","Name":"/app_web_1","State":{{"Status":"running","Running":true,"ExitCode":0,"StartedAt":"{}","FinishedAt":""}},"Config":{{"Image":"web","Tty":false,"Labels":{{}}}}}}"#,
+                started
+            );
+            future::ready(ContainerDetails::from_json(json.as_bytes()).map_err(Into::into)).boxed()
+        }
+
+        fn logs(
+            &self,
+            _id: &str,
+            options: &LogsOptions,
+        ) -> BoxStream<'static, Result<LogLine, Error>> {
+            let mut calls = self.calls.lock().unwrap();
+            calls.push(options.since);
+            let secs: Vec<i64> = match calls.len() {
+                1 => vec![1, 2],
+                // `since` is inclusive, so the last line comes again
+                2 => vec![2, 6],
+                _ => vec![],
+            };
+            let lines: Vec<Result<LogLine, Error>> = secs
+                .into_iter()
+                .map(|secs| {
+                    let mut line = LogLine::new(LogStream::Stdout, secs.to_string());
+                    line.timestamp = Some(at(secs));
+                    Ok(line)
+                })
+                .collect();
+            stream::iter(lines).boxed()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_reattach() {
+        let backend = Arc::new(Restarting {
+            calls: Mutex::new(vec![]),
+        });
+        let mut service = service();
+        service.replicas.truncate(1);
+        let mut collector = LogCollector::new(backend.clone(), &service);
+        collector.retry = Duration::from_millis(1);
+        collector.start();
+
+        let mut total = 0;
+        while total < 4 {
+            total = collector.next().await.unwrap().1;
+        }
+
+        let records = collector.since(0);
+        let raws: Vec<&str> = records.iter().map(|r| r.raw.as_str()).collect();
+        assert_eq!(raws, vec!["1", "2", "--- container restarted ---", "6"]);
+        assert_eq!(records[2].stream, LogStream::Event);
+        assert_eq!(backend.calls.lock().unwrap()[..2], [None, Some(at(2))]);
+    }
+
+    // Container whose stdout and stderr lines come out of order, and which stops once
+    struct Interleaved {
+        calls: Mutex<usize>,
+    }
+
+    impl Backend for Interleaved {
+        fn containers(
+            &self,
+            _filter: &ContainerFilter,
+        ) -> BoxFuture<'_, Result<Vec<Container>, Error>> {
+            future::ready(Ok(vec![])).boxed()
+        }
+
+        fn inspect(&self, _id: &str) -> BoxFuture<'_, Result<ContainerDetails, Error>> {
+            let json = r#"{"Id":"abc","Name":"/app_web_1","State":{"Status":"running","Running":true,"ExitCode":0,"StartedAt":"2021-05-01T10:00:00Z","FinishedAt":""},"Config":{"Image":"web","Tty":false,"Labels":{}}}"#;
+            future::ready(ContainerDetails::from_json(json.as_bytes()).map_err(Into::into)).boxed()
+        }
+
+        fn logs(
+            &self,
+            _id: &str,
+            _options: &LogsOptions,
+        ) -> BoxStream<'static, Result<LogLine, Error>> {
+            let mut calls = self.calls.lock().unwrap();
+            *calls += 1;
+            let written: Vec<(i64, LogStream, &str)> = match *calls {
+                1 => vec![
+                    (2, LogStream::Stdout, "out 2"),
+                    (1, LogStream::Stderr, "err 1"),
+                    (2, LogStream::Stdout, "out 2b"),
+                ],
+                // Resumed from the last time, which is sent again
+                2 => vec![
+                    (2, LogStream::Stdout, "out 2"),
+                    (2, LogStream::Stderr, "err 2"),
+                    (2, LogStream::Stdout, "out 2b"),
+                    (3, LogStream::Stdout, "out 3"),
+                ],
+                _ => vec![],
+            };
+            let lines: Vec<Result<LogLine, Error>> = written
+                .into_iter()
+                .map(|(secs, stream, text)| {
+                    let mut line = LogLine::new(stream, text.to_string());
+                    line.timestamp = Some(at(secs));
+                    Ok(line)
+                })
+                .collect();
+            stream::iter(lines).boxed()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_out_of_order() {
+        let backend = Arc::new(Interleaved {
+            calls: Mutex::new(0),
+        });
+        let mut service = service();
+        service.replicas.truncate(1);
+        service.multiline = Multiline::Off;
+        let mut collector = LogCollector::new(backend, &service);
+        collector.retry = Duration::from_millis(1);
+        collector.start();
+
+        let mut total = 0;
+        while total < 5 {
+            total = collector.next().await.unwrap().1;
+        }
+        let raws: Vec<String> = collector.since(0).into_iter().map(|r| r.raw).collect();
+        assert_eq!(raws, vec!["out 2", "err 1", "out 2b", "err 2", "out 3"]);
+    }
+
+    // Backend which can't be run at all
+    struct Missing;
+
+    impl Backend for Missing {
+        fn containers(
+            &self,
+            _filter: &ContainerFilter,
+        ) -> BoxFuture<'_, Result<Vec<Container>, Error>> {
+            future::ready(Err(Error::CommandNotFound("docker".into()))).boxed()
+        }
+
+        fn inspect(&self, _id: &str) -> BoxFuture<'_, Result<ContainerDetails, Error>> {
+            future::ready(Err(Error::CommandNotFound("docker".into()))).boxed()
+        }
+
+        fn logs(
+            &self,
+            _id: &str,
+            _options: &LogsOptions,
+        ) -> BoxStream<'static, Result<LogLine, Error>> {
+            stream::iter(vec![Err(Error::CommandNotFound("docker".into()))]).boxed()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_error() {
+        let mut service = service();
+        service.replicas.truncate(1);
+        let mut collector = LogCollector::new(Arc::new(Missing), &service);
+        collector.retry = Duration::from_millis(1);
+        collector.start();
+
+        collector.next().await.unwrap();
+        let records = collector.since(0);
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].stream, LogStream::Event);
+        assert!(records[0]
+            .raw
+            .starts_with("--- error: `docker` was not found"));
+        // It isn't retried
+        while !collector.is_finished() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(collector.since(0).len(), 1);
+    }
+
+    // Container which wrote one line per second from 5 to 9, and two at 7
+    struct Older;
+
+    impl Backend for Older {
+        fn containers(
+            &self,
+            _filter: &ContainerFilter,
+        ) -> BoxFuture<'_, Result<Vec<Container>, Error>> {
+            future::ready(Ok(vec![])).boxed()
+        }
+
+        fn inspect(&self, id: &str) -> BoxFuture<'_, Result<ContainerDetails, Error>> {
+            future::ready(Err(Error::NoSuchContainer(id.into()))).boxed()
+        }
+
+        fn logs(
+            &self,
+            _id: &str,
+            options: &LogsOptions,
+        ) -> BoxStream<'static, Result<LogLine, Error>> {
+            // Like Docker, `until` lets a line at that very time through
+            let written: Vec<(i64, &str)> =
+                vec![(5, "5"), (6, "6"), (7, "7a"), (7, "7b"), (8, "8"), (9, "9")];
+            let written: Vec<_> = written
+                .into_iter()
+                .filter(|(secs, _)| options.until.is_none_or(|until| at(*secs) <= until))
+                .collect();
+            let skip = options
+                .tail
+                .map_or(0, |tail| written.len().saturating_sub(tail));
+            let lines: Vec<Result<LogLine, Error>> = written[skip..]
+                .iter()
+                .map(|(secs, text)| {
+                    let mut line = LogLine::new(LogStream::Stdout, text.to_string());
+                    line.timestamp = Some(at(*secs));
+                    Ok(line)
+                })
+                .collect();
+            stream::iter(lines).boxed()
+        }
+    }
+
+    // Like `Older`, but its first fetch fails after a line
+    struct Flaky {
+        fetches: Mutex<usize>,
+    }
+
+    impl Backend for Flaky {
+        fn containers(
+            &self,
+            filter: &ContainerFilter,
+        ) -> BoxFuture<'_, Result<Vec<Container>, Error>> {
+            Older.containers(filter)
+        }
+
+        fn inspect(&self, id: &str) -> BoxFuture<'_, Result<ContainerDetails, Error>> {
+            Older.inspect(id)
+        }
+
+        fn logs(
+            &self,
+            id: &str,
+            options: &LogsOptions,
+        ) -> BoxStream<'static, Result<LogLine, Error>> {
+            let lines = Older.logs(id, options);
+            if options.until.is_none() {
+                return lines;
+            }
+            let mut fetches = self.fetches.lock().unwrap();
+            *fetches += 1;
+            if *fetches > 1 {
+                return lines;
+            }
+            let gone = Error::DaemonUnavailable("gone".into());
+            lines.take(1).chain(stream::iter(vec![Err(gone)])).boxed()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fetch_error() {
+        let mut service = service();
+        service.replicas.truncate(1);
+        let backend = Arc::new(Flaky {
+            fetches: Mutex::new(0),
+        });
+        let mut collector = LogCollector::new(backend, &service).with_backlog(Backlog {
+            tail: Some(3),
+            ..Backlog::default()
+        });
+        collector.start();
+        while collector.next().await.unwrap().1 < 3 {}
+
+        collector.fetch_more(2);
+        while collector.next().await.unwrap().1 < 4 {}
+        assert!(!collector.is_complete());
+        // Nothing of the failed fetch is kept, it's all fetched by trying again
+        while !collector.is_complete() {
+            collector.fetch_more(2);
+            tokio::task::yield_now().await;
+        }
+
+        let raws: Vec<String> = collector.since(0).into_iter().map(|r| r.raw).collect();
+        assert_eq!(raws[..3], ["7b", "8", "9"]);
+        assert!(raws[3].starts_with("--- error fetching older logs: cannot connect"));
+        assert_eq!(raws[4..], ["6", "7a", "5"]);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_more() {
+        let mut service = service();
+        service.replicas.truncate(1);
+        let mut collector = LogCollector::new(Arc::new(Older), &service).with_backlog(Backlog {
+            tail: Some(3),
+            ..Backlog::default()
+        });
+        collector.start();
+        while collector.next().await.unwrap().1 < 3 {}
+        assert!(!collector.is_complete());
+
+        // The line stored at the oldest time comes again, but not the other one at that time
+        collector.fetch_more(2);
+        while collector.next().await.unwrap().1 < 5 {}
+        collector.fetch_more(2);
+        while collector.next().await.unwrap().1 < 6 {}
+
+        let raws: Vec<String> = collector.since(0).into_iter().map(|r| r.raw).collect();
+        assert_eq!(raws, vec!["7b", "8", "9", "6", "7a", "5"]);
+        while !collector.is_complete() {
+            tokio::task::yield_now().await;
+        }
 
-            // println!("collector: poll_next: ready");
-            Poll::Ready(Some((self.service_name.clone(), count, diff)))
+        // Only the follower is left once the fetches are done
+        while !collector.tasks[1..]
+            .iter()
+            .all(|(_, task)| task.is_finished())
+        {
+            tokio::task::yield_now().await;
         }
+        collector.fetch_more(2);
+        assert_eq!(collector.tasks.len(), 1);
     }
 }
@@ -0,0 +1,237 @@
+use crate::ansi::strip;
+use crate::error::Error;
+use crate::record::LogRecord;
+use regex::Regex;
+use std::{fmt, str::FromStr};
+
+// Lines a record may be joined from, so that a runaway one doesn't grow without bounds
+const MAX_LINES: usize = 1000;
+
+/// How lines of a service are joined into records, e.g. the frames of a stack trace.
+#[derive(Debug, Clone, Default)]
+pub enum Multiline {
+    /// Every line is a record of its own.
+    Off,
+    /// Indented lines, and lines which look like the rest of a Java, Python or Rust stack
+    /// trace, continue the record before them.
+    #[default]
+    Auto,
+    /// Lines matching the pattern start a record, any other line continues it.
+    Start(Regex),
+}
+
+impl PartialEq for Multiline {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Multiline::Off, Multiline::Off) | (Multiline::Auto, Multiline::Auto) => true,
+            (Multiline::Start(a), Multiline::Start(b)) => a.as_str() == b.as_str(),
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for Multiline {
+    type Err = Error;
+
+    /// Parses `off`, `auto` or the pattern of lines starting a record, e.g. `^\d{4}-\d{2}-\d{2}`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "off" | "none" => Ok(Multiline::Off),
+            "auto" => Ok(Multiline::Auto),
+            pattern => Ok(Multiline::Start(Regex::new(pattern)?)),
+        }
+    }
+}
+
+impl fmt::Display for Multiline {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Multiline::Off => write!(f, "off"),
+            Multiline::Auto => write!(f, "auto"),
+            Multiline::Start(regex) => write!(f, "{}", regex),
+        }
+    }
+}
+
+impl Multiline {
+    // Whether the line continues the record so far
+    fn continues(&self, record: &LogRecord, line: &str) -> bool {
+        match self {
+            Multiline::Off => false,
+            Multiline::Start(regex) => !regex.is_match(line),
+            Multiline::Auto => {
+                let first = strip(record.head());
+                let indented = line.starts_with([' ', '\t']) && !line.trim().is_empty();
+                indented
+                    // Java
+                    || line.starts_with("Caused by: ")
+                    || line.starts_with("Suppressed: ")
+                    // The exception ending a Python traceback
+                    || (first.starts_with("Traceback (most recent call last):")
+                        && !line.trim().is_empty())
+                    // Rust puts the message after the location since 1.73, and hints after it
+                    || (first.contains(" panicked at ")
+                        && (line.starts_with("stack backtrace:")
+                            || line.starts_with("note: ")
+                            || (first.ends_with(':') && !record.raw.contains('\n'))))
+            }
+        }
+    }
+
+    // Whether nothing can continue the record after the line
+    fn ends(&self, record: &LogRecord, line: &str) -> bool {
+        match self {
+            Multiline::Auto => {
+                strip(record.head()).starts_with("Traceback (most recent call last):")
+                    && !line.starts_with(|c: char| c.is_whitespace())
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Joins lines of a replica into records as they come in.
+///
+/// A record is held until a line which doesn't continue it arrives, or `flush` is called when
+/// no more lines are expected for a while.
+pub(crate) struct Joiner {
+    multiline: Multiline,
+    pending: Option<(LogRecord, usize)>,
+}
+
+impl Joiner {
+    pub fn new(multiline: Multiline) -> Self {
+        Joiner {
+            multiline,
+            pending: None,
+        }
+    }
+
+    /// Takes the next line as a record, returns the record it completes, if any.
+    pub fn push(&mut self, record: LogRecord) -> Option<LogRecord> {
+        if let Some((pending, lines)) = &mut self.pending {
+            if pending.stream == record.stream
+                && *lines < MAX_LINES
+                && self.multiline.continues(pending, &strip(&record.raw))
+            {
+                let ends = self.multiline.ends(pending, &strip(&record.raw));
+                pending.raw.push('\n');
+                pending.raw.push_str(&record.raw);
+                *lines += 1;
+                return if ends { self.flush() } else { None };
+            }
+        }
+        if let Multiline::Off = self.multiline {
+            return Some(record);
+        }
+        self.pending
+            .replace((record, 1))
+            .map(|(pending, _)| pending)
+    }
+
+    /// Gives up waiting for lines continuing the held record.
+    pub fn flush(&mut self) -> Option<LogRecord> {
+        self.pending.take().map(|(pending, _)| pending)
+    }
+
+    pub fn is_pending(&self) -> bool {
+        self.pending.is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::LogStream;
+    use chrono::Utc;
+    use std::sync::Arc;
+
+    fn join(multiline: Multiline, lines: &[(LogStream, &str)]) -> Vec<String> {
+        let mut joiner = Joiner::new(multiline);
+        let mut records: Vec<LogRecord> = lines
+            .iter()
+            .filter_map(|(stream, raw)| {
+                joiner.push(LogRecord {
+                    seq: 0,
+                    timestamp: Utc::now(),
+                    stream: *stream,
+                    container: Arc::from("app_web_1"),
+                    replica: 1,
+                    raw: raw.to_string(),
+                })
+            })
+            .collect();
+        records.extend(joiner.flush());
+        records.into_iter().map(|record| record.raw).collect()
+    }
+
+    fn stderr(lines: &[&'static str]) -> Vec<(LogStream, &'static str)> {
+        lines
+            .iter()
+            .map(|line| (LogStream::Stderr, *line))
+            .collect()
+    }
+
+    #[test]
+    fn test_auto() {
+        let java = stderr(&[
+            "Exception in thread \"main\" java.lang.IllegalStateException: boom",
+            "\tat com.example.App.run(App.java:12)",
+            "Caused by: java.io.IOException: closed",
+            "\t... 3 more",
+            "INFO next",
+        ]);
+        assert_eq!(
+            join(Multiline::Auto, &java),
+            [
+                java[..4]
+                    .iter()
+                    .map(|(_, line)| *line)
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+                "INFO next".to_string()
+            ]
+        );
+
+        let python = stderr(&[
+            "Traceback (most recent call last):",
+            "  File \"app.py\", line 3, in <module>",
+            "    main()",
+            "ValueError: bad value",
+            "next",
+        ]);
+        let records = join(Multiline::Auto, &python);
+        assert_eq!(records.len(), 2);
+        assert!(records[0].ends_with("\nValueError: bad value"));
+
+        let rust = stderr(&[
+            "thread 'main' panicked at src/main.rs:2:5:",
+            "explicit panic",
+            "note: run with `RUST_BACKTRACE=1` environment variable to display a backtrace",
+            "next",
+        ]);
+        assert_eq!(join(Multiline::Auto, &rust).len(), 2);
+
+        // Lines of another stream don't continue a record
+        let mixed = [
+            (LogStream::Stdout, "a"),
+            (LogStream::Stderr, "  b"),
+            (LogStream::Stdout, "  c"),
+        ];
+        assert_eq!(join(Multiline::Auto, &mixed), ["a", "  b", "  c"]);
+    }
+
+    #[test]
+    fn test_start() {
+        let multiline: Multiline = r"^\d{4}-".parse().unwrap();
+        let lines = stderr(&["2021-05-01 ERROR failed", "details", "2021-05-01 INFO ok"]);
+        assert_eq!(
+            join(multiline, &lines),
+            ["2021-05-01 ERROR failed\ndetails", "2021-05-01 INFO ok"]
+        );
+
+        let lines = stderr(&["a", "  b"]);
+        assert_eq!(join(Multiline::Off, &lines), ["a", "  b"]);
+        assert!("(".parse::<Multiline>().is_err());
+    }
+}
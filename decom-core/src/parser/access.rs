@@ -0,0 +1,76 @@
+use super::{pretty, Format, Structured};
+use crate::record::Level;
+use chrono::{DateTime, Utc};
+use regex::Regex;
+use std::sync::OnceLock;
+
+// Common and combined log formats of nginx and Apache
+fn pattern() -> &'static Regex {
+    static PATTERN: OnceLock<Regex> = OnceLock::new();
+    PATTERN.get_or_init(|| {
+        Regex::new(
+            r#"^(\S+) \S+ (\S+) \[([^\]]+)\] "(\S+) (\S+)(?: (\S+))?" (\d{3}) (\d+|-)(?: "([^"]*)" "([^"]*)")?"#,
+        )
+        .expect("invalid access log pattern")
+    })
+}
+
+/// Parses a line of an access log. Its level tells the class of the response status.
+pub fn parse(raw: &str) -> Option<Structured> {
+    let captures = pattern().captures(raw.trim())?;
+    let get = |i: usize| captures.get(i).map_or("", |m| m.as_str());
+
+    let status: u16 = get(7).parse().ok()?;
+    let mut fields = vec![
+        ("remote_addr".to_string(), get(1).to_string()),
+        ("remote_user".to_string(), get(2).to_string()),
+        ("method".to_string(), get(4).to_string()),
+        ("path".to_string(), get(5).to_string()),
+        ("protocol".to_string(), get(6).to_string()),
+        ("status".to_string(), get(7).to_string()),
+        ("bytes".to_string(), get(8).to_string()),
+        ("referer".to_string(), get(9).to_string()),
+        ("user_agent".to_string(), get(10).to_string()),
+    ];
+    fields.retain(|(_, value)| !value.is_empty() && value != "-");
+    Some(Structured {
+        format: Format::Access,
+        level: Some(match status {
+            500..=599 => Level::Error,
+            400..=499 => Level::Warn,
+            _ => Level::Info,
+        }),
+        msg: Some(format!("{} {} {}", get(4), get(5), status)),
+        time: DateTime::parse_from_str(get(3), "%d/%b/%Y:%H:%M:%S %z")
+            .ok()
+            .map(|time| time.with_timezone(&Utc)),
+        pretty: Some(pretty(&fields)),
+        fields,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_parse() {
+        let line = parse(r#"172.18.0.1 - - [01/May/2021:10:00:00 +0000] "GET /api/users?page=2 HTTP/1.1" 503 154 "-" "curl/7.68.0""#).unwrap();
+        assert_eq!(line.level, Some(Level::Error));
+        assert_eq!(line.msg.as_deref(), Some("GET /api/users?page=2 503"));
+        assert_eq!(line.time, Some(Utc.timestamp_opt(1619863200, 0).unwrap()));
+        assert_eq!(line.field("user_agent"), Some("curl/7.68.0"));
+        assert_eq!(line.field("referer"), None);
+
+        // Common log format, without referer and user agent
+        let line =
+            parse(r#"10.0.0.1 - frank [10/Oct/2000:13:55:36 -0700] "POST /login HTTP/1.0" 401 -"#)
+                .unwrap();
+        assert_eq!(line.level, Some(Level::Warn));
+        assert_eq!(line.field("remote_user"), Some("frank"));
+        assert_eq!(line.field("bytes"), None);
+
+        assert_eq!(parse("GET /health 200"), None);
+    }
+}
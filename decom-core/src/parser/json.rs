@@ -0,0 +1,109 @@
+use super::{take, Format, Structured, LEVEL_KEYS, MSG_KEYS, TIME_KEYS};
+use crate::record::Level;
+use chrono::{DateTime, TimeZone, Utc};
+use serde_json::{Map, Value};
+
+/// Parses a line holding a JSON object.
+pub fn parse(raw: &str) -> Option<Structured> {
+    let raw = raw.trim();
+    if !raw.starts_with('{') {
+        return None;
+    }
+    let object: Map<String, Value> = serde_json::from_str(raw).ok()?;
+
+    let mut fields = vec![];
+    flatten("", &object, &mut fields);
+    Some(Structured {
+        format: Format::Json,
+        level: take(&mut fields, &LEVEL_KEYS).and_then(|value| level(&value)),
+        msg: take(&mut fields, &MSG_KEYS).map(|value| text(&value)),
+        time: take(&mut fields, &TIME_KEYS).and_then(|value| time(&value)),
+        fields: fields
+            .into_iter()
+            .map(|(key, value)| (key, text(&value)))
+            .collect(),
+        pretty: serde_json::to_string_pretty(&object).ok(),
+    })
+}
+
+// Lists leaves of nested objects with dotted keys
+fn flatten(prefix: &str, object: &Map<String, Value>, out: &mut Vec<(String, Value)>) {
+    for (key, value) in object {
+        let key = if prefix.is_empty() {
+            key.clone()
+        } else {
+            format!("{}.{}", prefix, key)
+        };
+        match value {
+            Value::Object(object) => flatten(&key, object, out),
+            value => out.push((key, value.clone())),
+        }
+    }
+}
+
+fn text(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        value => value.to_string(),
+    }
+}
+
+fn level(value: &Value) -> Option<Level> {
+    match value {
+        Value::String(s) => s.parse().ok(),
+        // bunyan and pino number levels by tens
+        Value::Number(n) => Some(match n.as_u64()? {
+            0..=10 => Level::Trace,
+            11..=20 => Level::Debug,
+            21..=30 => Level::Info,
+            31..=40 => Level::Warn,
+            41..=50 => Level::Error,
+            _ => Level::Fatal,
+        }),
+        _ => None,
+    }
+}
+
+fn time(value: &Value) -> Option<DateTime<Utc>> {
+    match value {
+        Value::String(s) => DateTime::parse_from_rfc3339(s)
+            .ok()
+            .map(|time| time.with_timezone(&Utc)),
+        // Milliseconds (pino) or seconds with a fraction (zap) since the Unix epoch
+        Value::Number(n) => {
+            let n = n.as_f64()?;
+            let millis = if n > 1e11 { n } else { n * 1000.0 };
+            Utc.timestamp_millis_opt(millis as i64).single()
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let pino = parse(r#"{"level":40,"time":1619863200123,"pid":7,"hostname":"web","msg":"slow request","req":{"method":"GET","url":"/"}}"#).unwrap();
+        assert_eq!(pino.level, Some(Level::Warn));
+        assert_eq!(pino.msg.as_deref(), Some("slow request"));
+        assert_eq!(
+            pino.time,
+            Some(Utc.timestamp_millis_opt(1619863200123).unwrap())
+        );
+        assert_eq!(pino.field("req.method"), Some("GET"));
+        assert_eq!(pino.field("pid"), Some("7"));
+        assert_eq!(pino.field("msg"), None);
+
+        let tracing = parse(r#"{"timestamp":"2021-05-01T10:00:00Z","level":"ERROR","fields":{"message":"boom","id":1},"target":"app"}"#).unwrap();
+        assert_eq!(tracing.level, Some(Level::Error));
+        assert_eq!(tracing.msg.as_deref(), Some("boom"));
+        assert_eq!(tracing.field("fields.id"), Some("1"));
+        assert!(tracing.pretty.unwrap().contains("\n  \"level\": \"ERROR\""));
+
+        assert_eq!(parse("GET /health 200"), None);
+        assert_eq!(parse("{not json"), None);
+        assert_eq!(parse("[1, 2]"), None);
+    }
+}
@@ -0,0 +1,100 @@
+use super::{pretty, take, Format, Structured, LEVEL_KEYS, MSG_KEYS, TIME_KEYS};
+use chrono::{DateTime, Utc};
+
+/// Parses a line of `key=value` pairs, where values with spaces are quoted.
+///
+/// Every word has to be a pair, and there have to be at least two of them, so that text which
+/// merely mentions `a=b` isn't taken for logfmt.
+pub fn parse(raw: &str) -> Option<Structured> {
+    let mut fields = pairs(raw)?;
+    if fields.len() < 2 {
+        return None;
+    }
+    let pretty = pretty(&fields);
+    Some(Structured {
+        format: Format::Logfmt,
+        level: take(&mut fields, &LEVEL_KEYS).and_then(|value| value.parse().ok()),
+        msg: take(&mut fields, &MSG_KEYS),
+        time: take(&mut fields, &TIME_KEYS).and_then(|value| {
+            DateTime::parse_from_rfc3339(&value)
+                .ok()
+                .map(|time| time.with_timezone(&Utc))
+        }),
+        fields,
+        pretty: Some(pretty),
+    })
+}
+
+fn pairs(raw: &str) -> Option<Vec<(String, String)>> {
+    let mut pairs = vec![];
+    let mut chars = raw.trim().chars().peekable();
+    while chars.peek().is_some() {
+        let mut key = String::new();
+        while let Some(c) = chars.next_if(|c| c.is_alphanumeric() || "_-.@/".contains(*c)) {
+            key.push(c);
+        }
+        if key.is_empty() || chars.next() != Some('=') {
+            return None;
+        }
+
+        let mut value = String::new();
+        if chars.next_if_eq(&'"').is_some() {
+            loop {
+                match chars.next()? {
+                    '"' => break,
+                    '\\' => value.push(chars.next()?),
+                    c => value.push(c),
+                }
+            }
+            // A quoted value ends the pair
+            if chars.peek().is_some_and(|c| !c.is_whitespace()) {
+                return None;
+            }
+        } else {
+            while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+                value.push(c);
+            }
+        }
+        pairs.push((key, value));
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+    }
+    Some(pairs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::record::Level;
+
+    #[test]
+    fn test_parse() {
+        let line = parse(
+            r#"time=2021-05-01T10:00:00Z level=warn msg="disk almost full" path=/var free=3%"#,
+        )
+        .unwrap();
+        assert_eq!(line.level, Some(Level::Warn));
+        assert_eq!(line.msg.as_deref(), Some("disk almost full"));
+        assert!(line.time.is_some());
+        assert_eq!(
+            line.fields,
+            [
+                ("path".to_string(), "/var".to_string()),
+                ("free".to_string(), "3%".to_string())
+            ]
+        );
+        assert!(line
+            .pretty
+            .unwrap()
+            .starts_with("time  = 2021-05-01T10:00:00Z\nlevel = warn"));
+
+        assert_eq!(
+            parse(r#"at=info empty= quoted="a \"b\"""#)
+                .unwrap()
+                .field("quoted"),
+            Some(r#"a "b""#)
+        );
+        assert_eq!(parse("retrying with timeout=5s"), None);
+        assert_eq!(parse("only=one"), None);
+        assert_eq!(parse(r#"a=1 b="open"#), None);
+    }
+}
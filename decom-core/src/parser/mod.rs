@@ -0,0 +1,122 @@
+mod access;
+mod json;
+mod logfmt;
+mod plain;
+
+use crate::record::Level;
+use chrono::{DateTime, Utc};
+use std::{fmt, str::FromStr};
+
+/// Format of a service's log lines.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// Whichever of the formats below a line is in.
+    #[default]
+    Auto,
+    Json,
+    /// `key=value` pairs.
+    Logfmt,
+    /// nginx or Apache combined access log.
+    Access,
+    /// Text with a severity word such as `ERROR` or `[warn]`.
+    Plain,
+    /// Lines aren't parsed.
+    Raw,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "auto" => Ok(Format::Auto),
+            "json" => Ok(Format::Json),
+            "logfmt" => Ok(Format::Logfmt),
+            "access" | "nginx" | "apache" => Ok(Format::Access),
+            "plain" => Ok(Format::Plain),
+            "raw" | "none" => Ok(Format::Raw),
+            _ => Err(format!(
+                "unknown format `{}`, expected auto, json, logfmt, access, plain or raw",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Format::Auto => "auto",
+            Format::Json => "json",
+            Format::Logfmt => "logfmt",
+            Format::Access => "access",
+            Format::Plain => "plain",
+            Format::Raw => "raw",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// What a parser found in a line.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Structured {
+    /// Format the line turned out to be in, never `Auto`.
+    pub format: Format,
+    pub level: Option<Level>,
+    pub msg: Option<String>,
+    /// When the application says the line was written, which may differ from Docker's time.
+    pub time: Option<DateTime<Utc>>,
+    /// Other fields in the order the parser lists them, with values as text.
+    pub fields: Vec<(String, String)>,
+    /// The whole line formatted over several lines, for a closer look.
+    pub pretty: Option<String>,
+}
+
+impl Structured {
+    pub fn field(&self, name: &str) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Parses a line in the format, trying each in turn for `Auto`.
+///
+/// Lines are parsed on demand rather than when they're stored, so that records stay as Docker
+/// gave them and any store can hold them.
+pub fn parse(raw: &str, format: Format) -> Option<Structured> {
+    match format {
+        Format::Auto => json::parse(raw)
+            .or_else(|| logfmt::parse(raw))
+            .or_else(|| access::parse(raw))
+            .or_else(|| plain::parse(raw)),
+        Format::Json => json::parse(raw),
+        Format::Logfmt => logfmt::parse(raw),
+        Format::Access => access::parse(raw),
+        Format::Plain => plain::parse(raw),
+        Format::Raw => None,
+    }
+}
+
+// Keys used for the well-known fields by bunyan, pino, zap, logrus, tracing and others
+const LEVEL_KEYS: [&str; 4] = ["level", "lvl", "severity", "log.level"];
+const MSG_KEYS: [&str; 4] = ["msg", "message", "fields.message", "@m"];
+const TIME_KEYS: [&str; 6] = ["time", "timestamp", "ts", "t", "@t", "@timestamp"];
+
+// Removes the first field with one of the keys
+fn take<T>(fields: &mut Vec<(String, T)>, keys: &[&str]) -> Option<T> {
+    let i = fields
+        .iter()
+        .position(|(key, _)| keys.contains(&key.as_str()))?;
+    Some(fields.remove(i).1)
+}
+
+fn pretty(fields: &[(String, String)]) -> String {
+    let width = fields.iter().map(|(key, _)| key.len()).max().unwrap_or(0);
+    fields
+        .iter()
+        .map(|(key, value)| format!("{:width$} = {}", key, value, width = width))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
@@ -0,0 +1,57 @@
+use super::{Format, Structured};
+use crate::record::Level;
+
+/// Recognizes a line of text telling its severity, with an uppercase word such as `WARN` or the
+/// value of a `level`, `lvl` or `severity` key. The line itself is left as it is.
+pub fn parse(raw: &str) -> Option<Structured> {
+    let words: Vec<&str> = raw
+        .split(|c: char| !c.is_ascii_alphabetic())
+        .filter(|word| !word.is_empty())
+        .collect();
+    for (i, word) in words.iter().enumerate() {
+        let key = ["level", "lvl", "severity"]
+            .iter()
+            .any(|key| word.eq_ignore_ascii_case(key));
+        let level = if key {
+            words.get(i + 1).and_then(|value| value.parse().ok())
+        } else if word.chars().all(|c| c.is_ascii_uppercase()) {
+            word.parse().ok()
+        } else {
+            None
+        };
+        if let Some(level) = level {
+            return Some(structured(level));
+        }
+    }
+    None
+}
+
+fn structured(level: Level) -> Structured {
+    Structured {
+        format: Format::Plain,
+        level: Some(level),
+        ..Structured::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn level(raw: &str) -> Option<Level> {
+        parse(raw).and_then(|line| line.level)
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            level("2021/05/01 [WARN] disk almost full"),
+            Some(Level::Warn)
+        );
+        assert_eq!(level("E0501 ERROR: connection refused"), Some(Level::Error));
+        assert_eq!(level("time: 10:00 level: debug"), Some(Level::Debug));
+        assert_eq!(level("GET /health 200"), None);
+        // Lowercase words only count as a value of a level key
+        assert_eq!(level("no error here"), None);
+    }
+}
@@ -0,0 +1,281 @@
+use crate::error::Error;
+use std::{
+    env,
+    ffi::OsString,
+    fs,
+    path::{Path, PathBuf},
+    result::Result,
+};
+
+const CANDIDATES: [&str; 4] = [
+    "compose.yaml",
+    "compose.yml",
+    "docker-compose.yaml",
+    "docker-compose.yml",
+];
+
+const OVERRIDES: [&str; 4] = [
+    "compose.override.yaml",
+    "compose.override.yml",
+    "docker-compose.override.yaml",
+    "docker-compose.override.yml",
+];
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ProjectOptions {
+    pub files: Vec<PathBuf>,
+    pub project_name: Option<String>,
+    pub project_directory: Option<PathBuf>,
+}
+
+impl ProjectOptions {
+    /// Fills options which are not given explicitly from `COMPOSE_FILE` and `COMPOSE_PROJECT_NAME`.
+    pub fn with_env(mut self) -> Self {
+        if self.files.is_empty() {
+            if let Some(value) = env::var_os("COMPOSE_FILE") {
+                let separator = env::var("COMPOSE_PATH_SEPARATOR")
+                    .ok()
+                    .and_then(|s| s.chars().next())
+                    .unwrap_or(if cfg!(windows) { ';' } else { ':' });
+                self.files = split_paths(&value.to_string_lossy(), separator);
+            }
+        }
+        if self.project_name.is_none() {
+            self.project_name = env::var("COMPOSE_PROJECT_NAME")
+                .ok()
+                .filter(|s| !s.is_empty());
+        }
+        self
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Project {
+    pub name: String,
+    pub directory: PathBuf,
+    pub files: Vec<PathBuf>,
+}
+
+impl Project {
+    pub fn discover(options: &ProjectOptions, cwd: &Path) -> Result<Project, Error> {
+        let files = if options.files.is_empty() {
+            let start = match &options.project_directory {
+                Some(dir) => cwd.join(dir),
+                None => cwd.to_path_buf(),
+            };
+            find_files(&start).ok_or(Error::NotComposeProject(start))?
+        } else {
+            options.files.iter().map(|f| cwd.join(f)).collect()
+        };
+
+        let directory = match &options.project_directory {
+            Some(dir) => cwd.join(dir),
+            None => files[0]
+                .parent()
+                .map(Path::to_path_buf)
+                .unwrap_or_else(|| cwd.to_path_buf()),
+        };
+
+        let name = match &options.project_name {
+            Some(name) => normalize_name(name),
+            None => files
+                .iter()
+                .rev()
+                .find_map(|f| fs::read_to_string(f).ok().and_then(|s| top_level_name(&s)))
+                .map(|name| normalize_name(&name))
+                .unwrap_or_else(|| {
+                    normalize_name(
+                        &directory
+                            .file_name()
+                            .map(|s| s.to_string_lossy().into_owned())
+                            .unwrap_or_default(),
+                    )
+                }),
+        };
+        if name.is_empty() {
+            return Err(Error::InvalidConfig(format!(
+                "unable to derive a project name from {}, use -p to set one",
+                directory.display()
+            )));
+        }
+
+        Ok(Project {
+            name,
+            directory,
+            files,
+        })
+    }
+
+    /// Global arguments passed to every `docker-compose` invocation for this project.
+    pub fn args(&self) -> Vec<OsString> {
+        let mut args: Vec<OsString> = vec![
+            "-p".into(),
+            self.name.clone().into(),
+            "--project-directory".into(),
+            self.directory.clone().into(),
+        ];
+        self.files.iter().for_each(|f| {
+            args.push("-f".into());
+            args.push(f.clone().into());
+        });
+        args
+    }
+}
+
+fn split_paths(value: &str, separator: char) -> Vec<PathBuf> {
+    value
+        .split(separator)
+        .filter(|s| !s.is_empty())
+        .map(PathBuf::from)
+        .collect()
+}
+
+fn find_files(start: &Path) -> Option<Vec<PathBuf>> {
+    start.ancestors().find_map(|dir| {
+        let file = CANDIDATES
+            .iter()
+            .map(|name| dir.join(name))
+            .find(|path| path.is_file())?;
+        let mut files = vec![file];
+        if let Some(file) = OVERRIDES
+            .iter()
+            .map(|name| dir.join(name))
+            .find(|path| path.is_file())
+        {
+            files.push(file);
+        }
+        Some(files)
+    })
+}
+
+// Only looks at the top-level `name:` key, so no YAML parser is needed.
+fn top_level_name(contents: &str) -> Option<String> {
+    contents.lines().find_map(|line| {
+        let value = line.strip_prefix("name:")?;
+        let value = value.split(" #").next().unwrap_or("").trim();
+        let value = value.trim_matches(|c| c == '"' || c == '\'');
+        if value.is_empty() || value.contains("${") {
+            None
+        } else {
+            Some(value.to_string())
+        }
+    })
+}
+
+// Same rule as Compose: lowercase letters, digits, dashes and underscores, starting with a letter or digit.
+fn normalize_name(name: &str) -> String {
+    name.to_lowercase()
+        .chars()
+        .filter(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || *c == '-' || *c == '_')
+        .skip_while(|c| !c.is_ascii_alphanumeric())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn workspace(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("decom-project-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("app/src")).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_normalize_name() {
+        assert_eq!(normalize_name("Periodic-Output"), "periodic-output");
+        assert_eq!(normalize_name("my.app"), "myapp");
+        assert_eq!(normalize_name("_app_1"), "app_1");
+        assert_eq!(normalize_name("..."), "");
+    }
+
+    #[test]
+    fn test_top_level_name() {
+        assert_eq!(
+            top_level_name("name: shop\nservices:\n  web:\n    name: nope\n"),
+            Some("shop".into())
+        );
+        assert_eq!(
+            top_level_name("name: \"shop\" # comment\n"),
+            Some("shop".into())
+        );
+        assert_eq!(top_level_name("name: ${PROJECT}\n"), None);
+        assert_eq!(top_level_name("services:\n  name:\n"), None);
+    }
+
+    #[test]
+    fn test_split_paths() {
+        assert_eq!(
+            split_paths("a.yml:b/c.yml:", ':'),
+            vec![PathBuf::from("a.yml"), PathBuf::from("b/c.yml")]
+        );
+    }
+
+    #[test]
+    fn test_discover_walks_up() {
+        let root = workspace("walk");
+        fs::write(root.join("app/docker-compose.yml"), "services: {}\n").unwrap();
+        fs::write(
+            root.join("app/docker-compose.override.yml"),
+            "services: {}\n",
+        )
+        .unwrap();
+
+        let project = Project::discover(&ProjectOptions::default(), &root.join("app/src")).unwrap();
+        assert_eq!(
+            project,
+            Project {
+                name: "app".into(),
+                directory: root.join("app"),
+                files: vec![
+                    root.join("app/docker-compose.yml"),
+                    root.join("app/docker-compose.override.yml"),
+                ],
+            }
+        );
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn test_discover_with_options() {
+        let root = workspace("options");
+        fs::write(root.join("app/compose.yaml"), "name: Shop\nservices: {}\n").unwrap();
+
+        let options = ProjectOptions {
+            files: vec!["app/compose.yaml".into()],
+            ..Default::default()
+        };
+        let project = Project::discover(&options, &root).unwrap();
+        assert_eq!(project.name, "shop");
+        assert_eq!(project.directory, root.join("app"));
+
+        let options = ProjectOptions {
+            project_name: Some("other".into()),
+            project_directory: Some("app".into()),
+            ..Default::default()
+        };
+        let project = Project::discover(&options, &root).unwrap();
+        assert_eq!(project.name, "other");
+        assert_eq!(project.files, vec![root.join("app/compose.yaml")]);
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn test_args() {
+        let project = Project {
+            name: "app".into(),
+            directory: "/work/app".into(),
+            files: vec!["/work/app/compose.yaml".into()],
+        };
+        let expected: Vec<OsString> = vec![
+            "-p".into(),
+            "app".into(),
+            "--project-directory".into(),
+            "/work/app".into(),
+            "-f".into(),
+            "/work/app/compose.yaml".into(),
+        ];
+        assert_eq!(project.args(), expected);
+    }
+}
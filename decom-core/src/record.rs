@@ -0,0 +1,167 @@
+use crate::ansi;
+use crate::backend::LogStream;
+use crate::parser::{self, Format, Structured};
+use chrono::{DateTime, Utc};
+use std::{borrow::Cow, str::FromStr, sync::Arc};
+
+/// A line captured from a container, or several joined into one such as a stack trace.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogRecord {
+    /// Position in the service's log, assigned when the record is stored.
+    pub seq: u64,
+    /// When the container wrote the line, or when decom received it if Docker didn't say.
+    pub timestamp: DateTime<Utc>,
+    pub stream: LogStream,
+    pub container: Arc<str>,
+    pub replica: u32,
+    pub raw: String,
+}
+
+impl LogRecord {
+    /// Whether the record passes a stream filter. Events such as restart markers always do.
+    pub fn matches(&self, stream: Option<LogStream>) -> bool {
+        match stream {
+            Some(stream) => self.stream == stream || self.stream == LogStream::Event,
+            None => true,
+        }
+    }
+
+    /// The record as it reads, without colours and other escape sequences in `raw`.
+    pub fn text(&self) -> Cow<'_, str> {
+        ansi::strip(&self.raw)
+    }
+
+    /// First line of the record, all of it unless lines such as a stack trace were joined to it.
+    pub fn head(&self) -> &str {
+        self.raw.split('\n').next().unwrap_or_default()
+    }
+
+    /// Lines joined to the first one.
+    pub fn continuation(&self) -> impl Iterator<Item = &str> {
+        self.raw.split('\n').skip(1)
+    }
+
+    /// Fields of the line if it's in a format decom understands, such as JSON.
+    pub fn parse(&self) -> Option<Structured> {
+        self.parse_as(Format::Auto)
+    }
+
+    /// Fields of the first line, taking it to be in the format.
+    pub fn parse_as(&self, format: Format) -> Option<Structured> {
+        parser::parse(&ansi::strip(self.head()), format)
+    }
+
+    /// Severity the line tells, from its structured fields, an uppercase word such as `WARN`
+    /// or the value of a `level`, `lvl` or `severity` key.
+    pub fn level(&self) -> Option<Level> {
+        self.level_as(Format::Auto)
+    }
+
+    /// Severity the line tells, taking it to be in the format.
+    pub fn level_as(&self, format: Format) -> Option<Level> {
+        self.parse_as(format)
+            .and_then(|structured| structured.level)
+    }
+}
+
+/// Severity of a line, from least to most severe.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Trace,
+    Debug,
+    Info,
+    Warn,
+    Error,
+    Fatal,
+}
+
+impl FromStr for Level {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "trace" => Ok(Level::Trace),
+            "debug" => Ok(Level::Debug),
+            "info" | "notice" => Ok(Level::Info),
+            "warn" | "warning" => Ok(Level::Warn),
+            "error" | "err" => Ok(Level::Error),
+            "fatal" | "critical" | "crit" | "panic" => Ok(Level::Fatal),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Splits the RFC 3339 timestamp which `docker logs --timestamps` puts in front of each line.
+pub fn split_timestamp(line: &str) -> (Option<DateTime<Utc>>, &str) {
+    match line.split_once(' ') {
+        Some((prefix, rest)) => match DateTime::parse_from_rfc3339(prefix) {
+            Ok(timestamp) => (Some(timestamp.with_timezone(&Utc)), rest),
+            Err(_) => (None, line),
+        },
+        None => match DateTime::parse_from_rfc3339(line) {
+            // An empty line still carries its timestamp
+            Ok(timestamp) => (Some(timestamp.with_timezone(&Utc)), ""),
+            Err(_) => (None, line),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_split_timestamp() {
+        let (timestamp, rest) = split_timestamp("2021-05-01T10:00:00.123456789Z GET /health 200");
+        assert_eq!(
+            timestamp,
+            Some(Utc.timestamp_opt(1619863200, 123456789).unwrap())
+        );
+        assert_eq!(rest, "GET /health 200");
+
+        let (timestamp, rest) = split_timestamp("2021-05-01T10:00:00Z");
+        assert!(timestamp.is_some());
+        assert_eq!(rest, "");
+
+        assert_eq!(
+            split_timestamp("no timestamp here"),
+            (None, "no timestamp here")
+        );
+    }
+
+    #[test]
+    fn test_level() {
+        let level = |raw: &str| {
+            LogRecord {
+                seq: 0,
+                timestamp: Utc::now(),
+                stream: LogStream::Stdout,
+                container: Arc::from("app_web_1"),
+                replica: 1,
+                raw: raw.to_string(),
+            }
+            .level()
+        };
+        assert_eq!(
+            level("2021/05/01 [WARN] disk almost full"),
+            Some(Level::Warn)
+        );
+        assert_eq!(level("time=10:00 level=error msg=boom"), Some(Level::Error));
+        assert_eq!(
+            level(r#"{"severity":"debug","message":"hi"}"#),
+            Some(Level::Debug)
+        );
+        assert_eq!(level(r#"{"level":50,"msg":"INFO"}"#), Some(Level::Error));
+        assert_eq!(
+            level(r#"10.0.0.1 - - [01/May/2021:10:00:00 +0000] "GET / HTTP/1.1" 404 0"#),
+            Some(Level::Warn)
+        );
+        assert_eq!(level("GET /health 200"), None);
+        assert_eq!(level("\x1b[33mWARN\x1b[0m slow"), Some(Level::Warn));
+        // Only the first line of a joined record tells it
+        assert_eq!(level("boom\n  at ERROR handler"), None);
+        // Lowercase words only count as a value of a level key
+        assert_eq!(level("no error here"), None);
+    }
+}
@@ -0,0 +1,291 @@
+use crate::backend::{LogLine, LogStream};
+use crate::docker::{ContainerConfig, ContainerDetails, ContainerState};
+use chrono::{DateTime, TimeZone, Utc};
+use std::{
+    collections::HashMap,
+    convert::TryInto,
+    io::{self, Read},
+};
+
+// A recording starts with a header:
+//
+//   [u8; 5]  magic bytes `DECOM`
+//   u8       version of the layout, 1
+//   str      project name
+//
+// followed by frames, all integers little endian:
+//
+//   u32  length of the rest
+//   u8   kind (1: containers, 2: line)
+//   i64  seconds since the Unix epoch
+//   u32  nanoseconds
+//
+// The time is when the containers were seen in that state, or when the line was written.
+// Containers frames hold the state of every container of the project:
+//
+//   u16  number of containers, then for each of them
+//   str  ID, name, image, status, started at and finished at
+//   u8   1 if running, 0 otherwise
+//   i64  exit code
+//   u8   1 if it has a TTY, 0 otherwise
+//   u16  number of labels, then a str key and value for each
+//
+// Line frames hold a line of a container's log:
+//
+//   str  container name
+//   u8   stream (1: stdout, 2: stderr)
+//   u32  text length, followed by the UTF-8 bytes
+//
+// where `str` is a u16 length followed by the UTF-8 bytes.
+
+const MAGIC: &[u8; 5] = b"DECOM";
+const VERSION: u8 = 1;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Frame {
+    Containers(DateTime<Utc>, Vec<ContainerDetails>),
+    /// A line of the named container, which always has a timestamp.
+    Line(String, LogLine),
+}
+
+pub fn encode_header(project: &str, out: &mut Vec<u8>) {
+    out.extend_from_slice(MAGIC);
+    out.push(VERSION);
+    put_str(out, project);
+}
+
+/// Reads the header and returns the project name.
+pub fn decode_header(input: &mut impl Read) -> io::Result<String> {
+    let mut header = [0; 6];
+    input
+        .read_exact(&mut header)
+        .map_err(|_| invalid("not a decom recording"))?;
+    if &header[..5] != MAGIC {
+        return Err(invalid("not a decom recording"));
+    }
+    if header[5] != VERSION {
+        return Err(invalid("unsupported version of recording"));
+    }
+    let mut len = [0; 2];
+    input.read_exact(&mut len)?;
+    let mut name = vec![0; u16::from_le_bytes(len) as usize];
+    input.read_exact(&mut name)?;
+    Ok(String::from_utf8_lossy(&name).into_owned())
+}
+
+pub fn encode(frame: &Frame, out: &mut Vec<u8>) {
+    let start = out.len();
+    out.extend_from_slice(&[0; 4]);
+    let (kind, time) = match frame {
+        Frame::Containers(time, _) => (1, *time),
+        Frame::Line(_, line) => (2, line.timestamp.unwrap_or_else(Utc::now)),
+    };
+    out.push(kind);
+    out.extend_from_slice(&time.timestamp().to_le_bytes());
+    out.extend_from_slice(&time.timestamp_subsec_nanos().to_le_bytes());
+    match frame {
+        Frame::Containers(_, containers) => {
+            let count = containers.len().min(u16::MAX as usize);
+            out.extend_from_slice(&(count as u16).to_le_bytes());
+            for details in &containers[..count] {
+                put_str(out, &details.id);
+                put_str(out, &details.name);
+                put_str(out, &details.config.image);
+                put_str(out, &details.state.status);
+                put_str(out, &details.state.started_at);
+                put_str(out, &details.state.finished_at);
+                out.push(details.state.running as u8);
+                out.extend_from_slice(&details.state.exit_code.to_le_bytes());
+                out.push(details.config.tty as u8);
+                let labels: Vec<_> = details.config.labels.iter().flatten().collect();
+                let count = labels.len().min(u16::MAX as usize);
+                out.extend_from_slice(&(count as u16).to_le_bytes());
+                for (key, value) in &labels[..count] {
+                    put_str(out, key);
+                    put_str(out, value);
+                }
+            }
+        }
+        Frame::Line(container, line) => {
+            put_str(out, container);
+            out.push(match line.stream {
+                LogStream::Stderr => 2,
+                _ => 1,
+            });
+            out.extend_from_slice(&(line.text.len() as u32).to_le_bytes());
+            out.extend_from_slice(line.text.as_bytes());
+        }
+    }
+
+    let len = (out.len() - start - 4) as u32;
+    out[start..start + 4].copy_from_slice(&len.to_le_bytes());
+}
+
+/// Reads one frame, or `None` at a clean end of input.
+pub fn decode(input: &mut impl Read) -> io::Result<Option<Frame>> {
+    let mut len = [0; 4];
+    match input.read_exact(&mut len) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    }
+    let mut body = vec![0; u32::from_le_bytes(len) as usize];
+    input.read_exact(&mut body)?;
+    decode_body(&body).map(Some)
+}
+
+fn decode_body(body: &[u8]) -> io::Result<Frame> {
+    let mut cursor = Cursor { body, at: 0 };
+    let kind = cursor.take(1)?[0];
+    let secs = i64::from_le_bytes(cursor.take(8)?.try_into().unwrap());
+    let nanos = u32::from_le_bytes(cursor.take(4)?.try_into().unwrap());
+    let time = Utc
+        .timestamp_opt(secs, nanos)
+        .single()
+        .ok_or_else(|| invalid("timestamp out of range"))?;
+    match kind {
+        1 => {
+            let count = cursor.u16()?;
+            let mut containers = Vec::with_capacity(count as usize);
+            for _ in 0..count {
+                let id = cursor.str()?;
+                let name = cursor.str()?;
+                let image = cursor.str()?;
+                let status = cursor.str()?;
+                let started_at = cursor.str()?;
+                let finished_at = cursor.str()?;
+                let running = cursor.take(1)?[0] != 0;
+                let exit_code = i64::from_le_bytes(cursor.take(8)?.try_into().unwrap());
+                let tty = cursor.take(1)?[0] != 0;
+                let mut labels = HashMap::new();
+                for _ in 0..cursor.u16()? {
+                    labels.insert(cursor.str()?, cursor.str()?);
+                }
+                containers.push(ContainerDetails {
+                    id,
+                    name,
+                    state: ContainerState {
+                        status,
+                        running,
+                        exit_code,
+                        started_at,
+                        finished_at,
+                    },
+                    config: ContainerConfig {
+                        image,
+                        tty,
+                        labels: Some(labels),
+                    },
+                });
+            }
+            Ok(Frame::Containers(time, containers))
+        }
+        2 => {
+            let container = cursor.str()?;
+            let stream = match cursor.take(1)?[0] {
+                2 => LogStream::Stderr,
+                _ => LogStream::Stdout,
+            };
+            let len = u32::from_le_bytes(cursor.take(4)?.try_into().unwrap()) as usize;
+            let text = String::from_utf8_lossy(cursor.take(len)?).into_owned();
+            Ok(Frame::Line(
+                container,
+                LogLine {
+                    stream,
+                    timestamp: Some(time),
+                    text,
+                },
+            ))
+        }
+        _ => Err(invalid("unknown kind of frame")),
+    }
+}
+
+fn put_str(out: &mut Vec<u8>, s: &str) {
+    let bytes = &s.as_bytes()[..s.len().min(u16::MAX as usize)];
+    out.extend_from_slice(&(bytes.len() as u16).to_le_bytes());
+    out.extend_from_slice(bytes);
+}
+
+struct Cursor<'a> {
+    body: &'a [u8],
+    at: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn take(&mut self, n: usize) -> io::Result<&'a [u8]> {
+        let bytes = self
+            .body
+            .get(self.at..self.at + n)
+            .ok_or_else(|| invalid("truncated frame"))?;
+        self.at += n;
+        Ok(bytes)
+    }
+
+    fn u16(&mut self) -> io::Result<u16> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn str(&mut self) -> io::Result<String> {
+        let len = self.u16()? as usize;
+        Ok(String::from_utf8_lossy(self.take(len)?).into_owned())
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_roundtrip() {
+        let time = Utc.timestamp_opt(1619863200, 123456789).unwrap();
+        let details = ContainerDetails {
+            id: "f00".into(),
+            name: "app_web_1".into(),
+            state: ContainerState {
+                status: "running".into(),
+                running: true,
+                exit_code: 0,
+                started_at: "2021-05-01T10:00:00Z".into(),
+                finished_at: "0001-01-01T00:00:00Z".into(),
+            },
+            config: ContainerConfig {
+                image: "nginx".into(),
+                tty: false,
+                labels: Some(
+                    vec![("com.docker.compose.service".into(), "web".into())]
+                        .into_iter()
+                        .collect(),
+                ),
+            },
+        };
+        let frames = vec![
+            Frame::Containers(time, vec![details]),
+            Frame::Line(
+                "app_web_1".into(),
+                LogLine {
+                    stream: LogStream::Stderr,
+                    timestamp: Some(time),
+                    text: "panicked at 'oops' ✗".into(),
+                },
+            ),
+        ];
+        let mut buf = vec![];
+        encode_header("app", &mut buf);
+        for frame in &frames {
+            encode(frame, &mut buf);
+        }
+
+        let mut input = buf.as_slice();
+        assert_eq!(decode_header(&mut input).unwrap(), "app");
+        assert_eq!(decode(&mut input).unwrap().as_ref(), Some(&frames[0]));
+        assert_eq!(decode(&mut input).unwrap().as_ref(), Some(&frames[1]));
+        assert_eq!(decode(&mut input).unwrap(), None);
+
+        assert!(decode_header(&mut &b"{\"level\":\"info\"}"[..]).is_err());
+    }
+}
@@ -0,0 +1,161 @@
+mod codec;
+mod recorder;
+mod replay;
+
+pub use recorder::Recorder;
+pub use replay::{Clock, Replay};
+
+use crate::backend::LogLine;
+use crate::docker::ContainerDetails;
+use crate::error::Error;
+use chrono::{DateTime, TimeZone, Utc};
+use codec::Frame;
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{self, BufReader, Read},
+    path::Path,
+};
+
+/// A session written by a `Recorder`: the states its containers went through and their logs.
+#[derive(Debug, Clone)]
+pub struct Recording {
+    project: String,
+    // States of the containers, in the order they were seen
+    snapshots: Vec<(DateTime<Utc>, Vec<ContainerDetails>)>,
+    // Lines of each container by name, in timestamp order
+    lines: HashMap<String, Vec<LogLine>>,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+}
+
+impl Recording {
+    pub fn open(path: &Path) -> Result<Recording, Error> {
+        File::open(path)
+            .and_then(|file| Recording::read(BufReader::new(file)))
+            .map_err(|e| {
+                let message = format!("cannot read {}: {}", path.display(), e);
+                Error::Io(io::Error::new(e.kind(), message))
+            })
+    }
+
+    /// Reads a whole recording. A frame cut short, as left by a recorder that was killed, ends
+    /// it.
+    pub fn read(mut input: impl Read) -> io::Result<Recording> {
+        let project = codec::decode_header(&mut input)?;
+        let mut snapshots: Vec<(DateTime<Utc>, Vec<ContainerDetails>)> = vec![];
+        let mut lines: HashMap<String, Vec<LogLine>> = HashMap::new();
+        loop {
+            match codec::decode(&mut input) {
+                Ok(Some(Frame::Containers(time, containers))) => snapshots.push((time, containers)),
+                Ok(Some(Frame::Line(container, line))) => {
+                    lines.entry(container).or_default().push(line)
+                }
+                Ok(None) => break,
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+                Err(e) => return Err(e),
+            }
+        }
+        snapshots.sort_by_key(|(time, _)| *time);
+        for lines in lines.values_mut() {
+            lines.sort_by_key(|line| line.timestamp);
+        }
+
+        // It starts when the containers were first seen, older lines are history by then
+        let last_line = lines.values().filter_map(|l| l.last()?.timestamp).max();
+        let start = snapshots
+            .first()
+            .map(|(time, _)| *time)
+            .or(last_line)
+            .unwrap_or_else(|| Utc.timestamp_opt(0, 0).unwrap());
+        let end = snapshots
+            .last()
+            .map(|(time, _)| *time)
+            .into_iter()
+            .chain(last_line)
+            .max()
+            .unwrap_or(start);
+        Ok(Recording {
+            project,
+            snapshots,
+            lines,
+            start,
+            end,
+        })
+    }
+
+    /// Name of the compose project recorded.
+    pub fn project(&self) -> &str {
+        &self.project
+    }
+
+    /// When the recording started.
+    pub fn start(&self) -> DateTime<Utc> {
+        self.start
+    }
+
+    /// Time of the last state or line recorded.
+    pub fn end(&self) -> DateTime<Utc> {
+        self.end
+    }
+
+    /// States of the containers as last seen at the time.
+    pub fn containers_at(&self, time: DateTime<Utc>) -> &[ContainerDetails] {
+        let seen = self.snapshots.partition_point(|(at, _)| *at <= time);
+        match seen.checked_sub(1) {
+            Some(i) => &self.snapshots[i].1,
+            None => &[],
+        }
+    }
+
+    /// Lines of the container in timestamp order.
+    pub fn lines(&self, container: &str) -> &[LogLine] {
+        self.lines.get(container).map_or(&[], Vec::as_slice)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::LogStream;
+
+    #[test]
+    fn test_read() {
+        let time = |secs: i64| Utc.timestamp_opt(1619863200 + secs, 0).unwrap();
+        let line = |secs, text: &str| {
+            Frame::Line(
+                "app_web_1".into(),
+                LogLine {
+                    stream: LogStream::Stdout,
+                    timestamp: Some(time(secs)),
+                    text: text.into(),
+                },
+            )
+        };
+        let mut buf = vec![];
+        codec::encode_header("app", &mut buf);
+        codec::encode(&Frame::Containers(time(10), vec![]), &mut buf);
+        codec::encode(&line(12, "second"), &mut buf);
+        // History fetched later is older than what was followed
+        codec::encode(&line(5, "first"), &mut buf);
+        codec::encode(&line(20, "cut short"), &mut buf);
+        buf.truncate(buf.len() - 3);
+
+        let recording = Recording::read(buf.as_slice()).unwrap();
+        assert_eq!(recording.project(), "app");
+        assert_eq!(recording.start(), time(10));
+        assert_eq!(recording.end(), time(12));
+        let texts: Vec<&str> = recording
+            .lines("app_web_1")
+            .iter()
+            .map(|line| line.text.as_str())
+            .collect();
+        assert_eq!(texts, ["first", "second"]);
+        assert!(recording.lines("app_db_1").is_empty());
+        assert!(Recording::read(&b"DECOM"[..]).is_err());
+        assert!(matches!(
+            Recording::open(Path::new("/nonexistent/app.decom")),
+            Err(Error::Io(e)) if e.kind() == io::ErrorKind::NotFound
+        ));
+    }
+}
@@ -0,0 +1,364 @@
+use super::codec::{self, Frame};
+use crate::backend::{Backend, ContainerFilter, LogLine, LogStream, LogsOptions};
+use crate::docker::{Container, ContainerDetails};
+use crate::error::Error;
+use chrono::{DateTime, Utc};
+use futures::{future::BoxFuture, prelude::*, stream::BoxStream};
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+// How long written frames may wait in the buffer
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+struct State {
+    out: BufWriter<Box<dyn Write + Send>>,
+    buf: Vec<u8>,
+    flushed: Instant,
+    // The first error writing, after which nothing more is written
+    error: Option<io::Error>,
+    // States of the containers as last written
+    containers: Vec<ContainerDetails>,
+    // Lines written of each container
+    written: HashMap<String, Written>,
+}
+
+// Oldest and newest times of the lines written of a container, with the lines written at them.
+// Followers resume with the lines at the last time seen, and history is fetched from the first
+// one, so those come again.
+struct Written {
+    oldest: DateTime<Utc>,
+    newest: DateTime<Utc>,
+    at_oldest: Vec<(LogStream, String)>,
+    at_newest: Vec<(LogStream, String)>,
+}
+
+impl Written {
+    // Takes note of the line, returns `false` if it was written already
+    fn add(&mut self, timestamp: DateTime<Utc>, line: &LogLine) -> bool {
+        let key = (line.stream, line.text.clone());
+        if timestamp > self.newest {
+            self.newest = timestamp;
+            self.at_newest.clear();
+        }
+        if timestamp < self.oldest {
+            self.oldest = timestamp;
+            self.at_oldest.clear();
+        }
+        let edges = [
+            (self.newest, &mut self.at_newest),
+            (self.oldest, &mut self.at_oldest),
+        ];
+        let new = !edges
+            .iter()
+            .any(|(at, lines)| *at == timestamp && lines.contains(&key));
+        if new {
+            for (at, lines) in edges {
+                if at == timestamp {
+                    lines.push(key.clone());
+                }
+            }
+        }
+        new
+    }
+}
+
+impl State {
+    fn write(&mut self, frame: &Frame) {
+        if self.error.is_some() {
+            return;
+        }
+        self.buf.clear();
+        codec::encode(frame, &mut self.buf);
+        let mut result = self.out.write_all(&self.buf);
+        if result.is_ok() && self.flushed.elapsed() >= FLUSH_INTERVAL {
+            self.flushed = Instant::now();
+            result = self.out.flush();
+        }
+        self.error = result.err();
+    }
+
+    // Writes the states if they changed
+    fn containers(&mut self, containers: Vec<ContainerDetails>) {
+        if containers != self.containers {
+            self.write(&Frame::Containers(Utc::now(), containers.clone()));
+            self.containers = containers;
+        }
+    }
+
+    fn line(&mut self, container: &str, line: &LogLine) {
+        let timestamp = match line.timestamp {
+            Some(timestamp) => timestamp,
+            None => {
+                let line = LogLine {
+                    timestamp: Some(Utc::now()),
+                    ..line.clone()
+                };
+                return self.write(&Frame::Line(container.to_string(), line));
+            }
+        };
+        let written = self
+            .written
+            .entry(container.to_string())
+            .or_insert_with(|| Written {
+                oldest: timestamp,
+                newest: timestamp,
+                at_oldest: vec![],
+                at_newest: vec![],
+            });
+        if !written.add(timestamp, line) {
+            return;
+        }
+        self.write(&Frame::Line(container.to_string(), line.clone()));
+    }
+}
+
+/// A `Backend` writing what passes through it to a recording, to be played back later by a
+/// `Replay`.
+///
+/// The states of the containers are written whenever they are listed or inspected and have
+/// changed, and lines of their logs as they are streamed, each of them once.
+#[derive(Clone)]
+pub struct Recorder {
+    backend: Arc<dyn Backend>,
+    state: Arc<Mutex<State>>,
+}
+
+impl Recorder {
+    /// Records the project into a new file at the path.
+    pub fn create(path: &Path, backend: Arc<dyn Backend>, project: &str) -> Result<Self, Error> {
+        let file = File::create(path).map_err(|e| {
+            let message = format!("cannot create {}: {}", path.display(), e);
+            Error::Io(io::Error::new(e.kind(), message))
+        })?;
+        Ok(Recorder::new(file, backend, project)?)
+    }
+
+    pub fn new(
+        out: impl Write + Send + 'static,
+        backend: Arc<dyn Backend>,
+        project: &str,
+    ) -> io::Result<Self> {
+        let mut out = BufWriter::new(Box::new(out) as Box<dyn Write + Send>);
+        let mut header = vec![];
+        codec::encode_header(project, &mut header);
+        out.write_all(&header)?;
+        Ok(Recorder {
+            backend,
+            state: Arc::new(Mutex::new(State {
+                out,
+                buf: vec![],
+                flushed: Instant::now(),
+                error: None,
+                containers: vec![],
+                written: HashMap::new(),
+            })),
+        })
+    }
+
+    /// Writes out what is buffered. Fails with the first error writing the recording, if any.
+    pub fn flush(&self) -> io::Result<()> {
+        let mut state = self.state.lock().expect("failed to lock");
+        if let Some(e) = state.error.take() {
+            return Err(e);
+        }
+        state.out.flush()
+    }
+}
+
+impl Backend for Recorder {
+    fn containers(&self, filter: &ContainerFilter) -> BoxFuture<'_, Result<Vec<Container>, Error>> {
+        let filter = filter.clone();
+        async move {
+            let containers = self.backend.containers(&filter).await?;
+            // Only containers which changed are inspected again
+            let known = self
+                .state
+                .lock()
+                .expect("failed to lock")
+                .containers
+                .clone();
+            let mut states = vec![];
+            for container in &containers {
+                let same = known
+                    .iter()
+                    .find(|d| d.id == container.id && d.state.status == container.state);
+                match same {
+                    Some(details) => states.push(details.clone()),
+                    // Unless it went away in the meantime
+                    None => states.extend(self.backend.inspect(&container.id).await.ok()),
+                }
+            }
+            self.state
+                .lock()
+                .expect("failed to lock")
+                .containers(states);
+            Ok(containers)
+        }
+        .boxed()
+    }
+
+    fn inspect(&self, id: &str) -> BoxFuture<'_, Result<ContainerDetails, Error>> {
+        let id = id.to_string();
+        async move {
+            let details = self.backend.inspect(&id).await?;
+            let mut state = self.state.lock().expect("failed to lock");
+            let mut containers = state.containers.clone();
+            match containers.iter_mut().find(|d| d.name == details.name) {
+                Some(known) => *known = details.clone(),
+                None => containers.push(details.clone()),
+            }
+            state.containers(containers);
+            Ok(details)
+        }
+        .boxed()
+    }
+
+    fn logs(&self, id: &str, options: &LogsOptions) -> BoxStream<'static, Result<LogLine, Error>> {
+        let state = self.state.clone();
+        let container = id.to_string();
+        self.backend
+            .logs(id, options)
+            .inspect(move |line| {
+                if let Ok(line) = line {
+                    state.lock().expect("failed to lock").line(&container, line);
+                }
+            })
+            .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::docker::{ContainerConfig, ContainerState};
+    use crate::recording::Recording;
+    use chrono::TimeZone;
+    use futures::{future, stream};
+
+    // Shared buffer the recording is written to
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    struct Fake;
+
+    fn time(secs: i64) -> DateTime<Utc> {
+        Utc.timestamp_opt(1619863200 + secs, 0).unwrap()
+    }
+
+    impl Backend for Fake {
+        fn containers(
+            &self,
+            _filter: &ContainerFilter,
+        ) -> BoxFuture<'_, Result<Vec<Container>, Error>> {
+            future::ready(Ok(vec![Container {
+                id: "f00".into(),
+                name: "app_web_1".into(),
+                labels: HashMap::new(),
+                state: "running".into(),
+            }]))
+            .boxed()
+        }
+
+        fn inspect(&self, id: &str) -> BoxFuture<'_, Result<ContainerDetails, Error>> {
+            future::ready(Ok(ContainerDetails {
+                id: "f00".into(),
+                name: "app_web_1".into(),
+                state: ContainerState {
+                    status: "running".into(),
+                    running: true,
+                    exit_code: 0,
+                    started_at: id.into(),
+                    finished_at: String::new(),
+                },
+                config: ContainerConfig {
+                    image: "nginx".into(),
+                    tty: false,
+                    labels: None,
+                },
+            }))
+            .boxed()
+        }
+
+        // Lines at or after `since`, as a follower resuming sees them again
+        fn logs(
+            &self,
+            _id: &str,
+            options: &LogsOptions,
+        ) -> BoxStream<'static, Result<LogLine, Error>> {
+            let lines: Vec<_> = [(1, "one"), (2, "two"), (2, "two again"), (3, "three")]
+                .iter()
+                .filter(|(secs, _)| options.since.is_none_or(|since| time(*secs) >= since))
+                .filter(|(secs, _)| options.until.is_none_or(|until| time(*secs) < until))
+                .map(|(secs, text)| {
+                    Ok(LogLine {
+                        stream: LogStream::Stdout,
+                        timestamp: Some(time(*secs)),
+                        text: text.to_string(),
+                    })
+                })
+                .collect();
+            stream::iter(lines).boxed()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_record() {
+        let buffer = Buffer::default();
+        let recorder = Recorder::new(buffer.clone(), Arc::new(Fake), "app").unwrap();
+        let filter = ContainerFilter::default();
+        recorder.containers(&filter).await.unwrap();
+        // Nothing changed
+        recorder.containers(&filter).await.unwrap();
+        // Restarted
+        recorder.inspect("later").await.unwrap();
+
+        let logs = |since, until| LogsOptions {
+            since,
+            until,
+            ..LogsOptions::default()
+        };
+        let lines: Vec<_> = recorder
+            .logs("app_web_1", &logs(Some(time(2)), None))
+            .collect()
+            .await;
+        assert_eq!(lines.len(), 3);
+        recorder
+            .logs("app_web_1", &logs(Some(time(3)), None))
+            .collect::<Vec<_>>()
+            .await;
+        recorder
+            .logs("app_web_1", &logs(None, Some(time(2))))
+            .collect::<Vec<_>>()
+            .await;
+        recorder.flush().unwrap();
+
+        let recording = Recording::read(buffer.0.lock().unwrap().as_slice()).unwrap();
+        let texts: Vec<&str> = recording
+            .lines("app_web_1")
+            .iter()
+            .map(|line| line.text.as_str())
+            .collect();
+        // Lines at the same time are all there, once
+        assert_eq!(texts, ["one", "two", "two again", "three"]);
+        let now = Utc::now();
+        assert_eq!(recording.containers_at(now)[0].state.started_at, "later");
+        assert_eq!(recording.snapshots.len(), 2);
+    }
+}
@@ -0,0 +1,355 @@
+use super::Recording;
+use crate::backend::{Backend, ContainerFilter, LogLine, LogsOptions};
+use crate::docker::{Container, ContainerDetails};
+use crate::docker_compose::{merge_service_names, Service, SERVICE_LABEL};
+use crate::error::Error;
+use chrono::{DateTime, Utc};
+use futures::{
+    future::{self, BoxFuture},
+    prelude::*,
+    stream::{self, BoxStream},
+};
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+// Longest wait for the clock, so that a change of speed soon applies
+const TICK: Duration = Duration::from_millis(50);
+
+const MIN_SPEED: f64 = 1.0 / 16.0;
+const MAX_SPEED: f64 = 64.0;
+
+struct ClockState {
+    // Time shown when the clock was last set
+    at: DateTime<Utc>,
+    since: Instant,
+    speed: f64,
+    paused: bool,
+    end: DateTime<Utc>,
+}
+
+impl ClockState {
+    fn now(&self) -> DateTime<Utc> {
+        if self.paused {
+            return self.at;
+        }
+        let elapsed = self.since.elapsed().mul_f64(self.speed);
+        let elapsed =
+            chrono::Duration::from_std(elapsed).unwrap_or_else(|_| chrono::Duration::zero());
+        (self.at + elapsed).min(self.end)
+    }
+
+    fn set(&mut self) {
+        self.at = self.now();
+        self.since = Instant::now();
+    }
+}
+
+/// Time of a replay, which runs from the start of the recording to its end at a speed.
+#[derive(Clone)]
+pub struct Clock {
+    state: Arc<Mutex<ClockState>>,
+}
+
+impl Clock {
+    fn new(start: DateTime<Utc>, end: DateTime<Utc>) -> Self {
+        Clock {
+            state: Arc::new(Mutex::new(ClockState {
+                at: start,
+                since: Instant::now(),
+                speed: 1.0,
+                paused: false,
+                end,
+            })),
+        }
+    }
+
+    pub fn now(&self) -> DateTime<Utc> {
+        self.state.lock().expect("failed to lock").now()
+    }
+
+    pub fn speed(&self) -> f64 {
+        self.state.lock().expect("failed to lock").speed
+    }
+
+    /// Plays at the speed, e.g. 2.0 for twice as fast as recorded, from 1/16 up to 64.
+    pub fn set_speed(&self, speed: f64) {
+        let mut state = self.state.lock().expect("failed to lock");
+        state.set();
+        state.speed = speed.clamp(MIN_SPEED, MAX_SPEED);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.state.lock().expect("failed to lock").paused
+    }
+
+    pub fn set_paused(&self, paused: bool) {
+        let mut state = self.state.lock().expect("failed to lock");
+        state.set();
+        state.paused = paused;
+    }
+
+    /// Whether the end of the recording has been reached.
+    pub fn is_over(&self) -> bool {
+        let state = self.state.lock().expect("failed to lock");
+        state.now() >= state.end
+    }
+
+    // Real time until the clock shows the time, a tick at most
+    fn wait(&self, time: DateTime<Utc>) -> Duration {
+        let state = self.state.lock().expect("failed to lock");
+        if state.paused {
+            return TICK;
+        }
+        (time - state.now())
+            .to_std()
+            .map_or(Duration::from_secs(0), |left| left.div_f64(state.speed))
+            .min(TICK)
+    }
+}
+
+/// A `Backend` playing a recording back as if its containers were running, without Docker.
+///
+/// Containers are in the state they were recorded in at the time of the clock, and their logs
+/// are followed as the clock reaches the times lines were written at. Lines from before the
+/// recording started are there from the start.
+#[derive(Clone)]
+pub struct Replay {
+    recording: Arc<Recording>,
+    clock: Clock,
+}
+
+impl Replay {
+    pub fn new(recording: Recording) -> Self {
+        Replay {
+            clock: Clock::new(recording.start(), recording.end()),
+            recording: Arc::new(recording),
+        }
+    }
+
+    pub fn recording(&self) -> &Recording {
+        &self.recording
+    }
+
+    pub fn clock(&self) -> &Clock {
+        &self.clock
+    }
+
+    /// Services of the project at the time of the clock, ordered by name.
+    pub fn services(&self) -> Vec<Service> {
+        let containers = self.containers_at();
+        let mut names: Vec<String> = containers
+            .iter()
+            .filter_map(|c| c.label(SERVICE_LABEL).map(String::from))
+            .collect();
+        names.sort();
+        names.dedup();
+        merge_service_names(names, containers)
+    }
+
+    fn containers_at(&self) -> Vec<Container> {
+        self.recording
+            .containers_at(self.clock.now())
+            .iter()
+            .cloned()
+            .map(Container::from)
+            .collect()
+    }
+}
+
+impl Backend for Replay {
+    fn containers(&self, filter: &ContainerFilter) -> BoxFuture<'_, Result<Vec<Container>, Error>> {
+        let mut containers: Vec<Container> = self
+            .containers_at()
+            .into_iter()
+            .filter(|c| filter.matches(c))
+            .collect();
+        containers.sort_by(|a, b| a.name.cmp(&b.name));
+        future::ready(Ok(containers)).boxed()
+    }
+
+    fn inspect(&self, id: &str) -> BoxFuture<'_, Result<ContainerDetails, Error>> {
+        let details = self
+            .recording
+            .containers_at(self.clock.now())
+            .iter()
+            .find(|d| d.name == id || d.id == id)
+            .cloned()
+            .ok_or_else(|| Error::NoSuchContainer(format!("No such container: {}", id)));
+        future::ready(details).boxed()
+    }
+
+    /// Lines of the container, which are followed until it stops. Lines the clock hasn't
+    /// reached yet count as not written, also for `tail`.
+    fn logs(&self, id: &str, options: &LogsOptions) -> BoxStream<'static, Result<LogLine, Error>> {
+        let recording = self.recording.clone();
+        let clock = self.clock.clone();
+        let container = id.to_string();
+        let (follow, until) = (options.follow, options.until);
+        let time = |line: &LogLine| line.timestamp.unwrap_or_else(Utc::now);
+
+        let lines = recording.lines(&container);
+        let mut first = match options.since {
+            Some(since) => lines.partition_point(|line| time(line) < since),
+            None => 0,
+        };
+        if let Some(tail) = options.tail {
+            let now = clock.now();
+            let written = lines.partition_point(|line| {
+                time(line) <= now && until.is_none_or(|until| time(line) < until)
+            });
+            first = first.max(written.saturating_sub(tail));
+        }
+
+        stream::unfold(first, move |next| {
+            let recording = recording.clone();
+            let clock = clock.clone();
+            let container = container.clone();
+            async move {
+                let lines = recording.lines(&container);
+                loop {
+                    let line = lines
+                        .get(next)
+                        .filter(|line| until.is_none_or(|until| time(line) < until));
+                    let now = clock.now();
+                    match line {
+                        Some(line) if time(line) <= now => {
+                            return Some((Ok(line.clone()), next + 1));
+                        }
+                        _ if !follow => return None,
+                        _ if !is_running(&recording, &container, now) => return None,
+                        Some(line) => tokio::time::sleep(clock.wait(time(line))).await,
+                        None => tokio::time::sleep(TICK).await,
+                    }
+                }
+            }
+        })
+        .boxed()
+    }
+}
+
+fn is_running(recording: &Recording, container: &str, time: DateTime<Utc>) -> bool {
+    recording
+        .containers_at(time)
+        .iter()
+        .any(|d| (d.name == container || d.id == container) && d.state.running)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::LogStream;
+    use crate::docker::{ContainerConfig, ContainerState};
+    use crate::docker_compose::PROJECT_LABEL;
+    use crate::recording::codec::{self, Frame};
+    use chrono::TimeZone;
+
+    fn time(millis: i64) -> DateTime<Utc> {
+        Utc.timestamp_millis_opt(1619863200000 + millis).unwrap()
+    }
+
+    fn details(running: bool) -> ContainerDetails {
+        ContainerDetails {
+            id: "f00".into(),
+            name: "app_web_1".into(),
+            state: ContainerState {
+                status: if running { "running" } else { "exited" }.into(),
+                running,
+                exit_code: if running { 0 } else { 1 },
+                started_at: "2021-05-01T10:00:00Z".into(),
+                finished_at: String::new(),
+            },
+            config: ContainerConfig {
+                image: "nginx".into(),
+                tty: false,
+                labels: Some(
+                    vec![
+                        (PROJECT_LABEL.to_string(), "app".to_string()),
+                        (SERVICE_LABEL.to_string(), "web".to_string()),
+                    ]
+                    .into_iter()
+                    .collect(),
+                ),
+            },
+        }
+    }
+
+    fn replay() -> Replay {
+        let line = |millis, text: &str| {
+            Frame::Line(
+                "app_web_1".into(),
+                LogLine {
+                    stream: LogStream::Stdout,
+                    timestamp: Some(time(millis)),
+                    text: text.into(),
+                },
+            )
+        };
+        let mut buf = vec![];
+        codec::encode_header("app", &mut buf);
+        codec::encode(&line(-1000, "history"), &mut buf);
+        codec::encode(&Frame::Containers(time(0), vec![details(true)]), &mut buf);
+        codec::encode(&line(100, "live"), &mut buf);
+        codec::encode(&line(300, "last"), &mut buf);
+        codec::encode(
+            &Frame::Containers(time(400), vec![details(false)]),
+            &mut buf,
+        );
+        Replay::new(Recording::read(buf.as_slice()).unwrap())
+    }
+
+    async fn texts(replay: &Replay, options: &LogsOptions) -> Vec<String> {
+        replay
+            .logs("app_web_1", options)
+            .map(|line| line.unwrap().text)
+            .collect()
+            .await
+    }
+
+    #[tokio::test]
+    async fn test_replay() {
+        let replay = replay();
+        let services = replay.services();
+        assert_eq!(services.len(), 1);
+        assert_eq!(services[0].replicas[0].container_name, "app_web_1");
+        let filter = ContainerFilter::default().label(PROJECT_LABEL, "other");
+        assert!(replay.containers(&filter).await.unwrap().is_empty());
+
+        // Only what was written by the start of the recording
+        assert_eq!(texts(&replay, &LogsOptions::default()).await, ["history"]);
+
+        // Followed as the clock goes on, until the container stops
+        replay.clock().set_speed(4.0);
+        let follow = LogsOptions {
+            follow: true,
+            ..LogsOptions::default()
+        };
+        assert_eq!(texts(&replay, &follow).await, ["history", "live", "last"]);
+        assert!(replay.clock().is_over());
+        let details = replay.inspect("app_web_1").await.unwrap();
+        assert_eq!(details.state.exit_code, 1);
+
+        let older = LogsOptions {
+            until: Some(time(300)),
+            tail: Some(1),
+            ..LogsOptions::default()
+        };
+        assert_eq!(texts(&replay, &older).await, ["live"]);
+    }
+
+    #[test]
+    fn test_clock() {
+        let clock = Clock::new(time(0), time(1000));
+        clock.set_paused(true);
+        let now = clock.now();
+        std::thread::sleep(Duration::from_millis(10));
+        assert_eq!(clock.now(), now);
+        clock.set_speed(1000.0);
+        assert_eq!(clock.speed(), MAX_SPEED);
+        clock.set_paused(false);
+        std::thread::sleep(Duration::from_millis(20));
+        assert!(clock.is_over());
+        assert_eq!(clock.now(), time(1000));
+    }
+}
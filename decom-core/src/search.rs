@@ -0,0 +1,218 @@
+use crate::error::Error;
+use crate::store::LogStore;
+use regex::{Regex, RegexBuilder};
+use std::collections::HashSet;
+use std::ops::Range;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+// Records matched between yields to other tasks
+const CHUNK: u64 = 1000;
+
+// How often to look for new records once all have been searched
+const POLL: Duration = Duration::from_millis(200);
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct QueryOptions {
+    /// Take the pattern as a regular expression instead of literal text.
+    pub regex: bool,
+    pub ignore_case: bool,
+}
+
+/// Text to look for in records.
+#[derive(Debug, Clone)]
+pub struct Query {
+    pattern: String,
+    options: QueryOptions,
+    regex: Regex,
+}
+
+impl Query {
+    pub fn new(pattern: &str, options: QueryOptions) -> Result<Query, Error> {
+        let source = if options.regex {
+            pattern.to_string()
+        } else {
+            regex::escape(pattern)
+        };
+        let regex = RegexBuilder::new(&source)
+            .case_insensitive(options.ignore_case)
+            .build()?;
+        Ok(Query {
+            pattern: pattern.to_string(),
+            options,
+            regex,
+        })
+    }
+
+    pub fn pattern(&self) -> &str {
+        &self.pattern
+    }
+
+    pub fn options(&self) -> QueryOptions {
+        self.options
+    }
+
+    pub fn is_match(&self, text: &str) -> bool {
+        self.regex.is_match(text)
+    }
+
+    /// Byte ranges of the matches in the text.
+    pub fn find_iter<'a>(&'a self, text: &'a str) -> impl Iterator<Item = Range<usize>> + 'a {
+        self.regex
+            .find_iter(text)
+            .filter(|m| !m.range().is_empty())
+            .map(|m| m.range())
+    }
+}
+
+#[derive(Default)]
+struct Matches {
+    seqs: HashSet<u64>,
+    // Next sequence number to search
+    cursor: u64,
+    done: bool,
+}
+
+/// Records of a store matching a query.
+///
+/// They're searched by a task on the Tokio runtime, a chunk at a time without holding the
+/// store, so that a large store doesn't block collectors or drawing. The task keeps searching
+/// new records until the search is dropped.
+pub struct Search {
+    query: Query,
+    matches: Arc<Mutex<Matches>>,
+    task: JoinHandle<()>,
+}
+
+impl Search {
+    pub(crate) fn start(logs: Arc<Mutex<Box<dyn LogStore>>>, query: Query) -> Self {
+        let matches = Arc::new(Mutex::new(Matches::default()));
+        let task = tokio::spawn({
+            let matches = matches.clone();
+            let query = query.clone();
+            async move {
+                loop {
+                    let cursor = matches.lock().expect("failed to lock").cursor;
+                    let (records, to, next) = {
+                        let logs = logs.lock().expect("failed to lock");
+                        let from = cursor.max(logs.first_seq());
+                        let next = logs.next_seq();
+                        let to = next.min(from + CHUNK).max(from);
+                        (logs.range(from..to), to, next)
+                    };
+
+                    {
+                        let mut matches = matches.lock().expect("failed to lock");
+                        matches.seqs.extend(
+                            records
+                                .iter()
+                                .filter(|record| query.is_match(&record.text()))
+                                .map(|record| record.seq),
+                        );
+                        matches.cursor = to;
+                        matches.done = to >= next;
+                    }
+
+                    if to >= next {
+                        tokio::time::sleep(POLL).await;
+                    } else {
+                        tokio::task::yield_now().await;
+                    }
+                }
+            }
+        });
+        Search {
+            query,
+            matches,
+            task,
+        }
+    }
+
+    pub fn query(&self) -> &Query {
+        &self.query
+    }
+
+    pub fn contains(&self, seq: u64) -> bool {
+        let matches = self.matches.lock().expect("failed to lock");
+        matches.seqs.contains(&seq)
+    }
+
+    /// Number of matching records found so far.
+    pub fn len(&self) -> usize {
+        self.matches.lock().expect("failed to lock").seqs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Whether every record stored so far has been searched.
+    pub fn is_done(&self) -> bool {
+        self.matches.lock().expect("failed to lock").done
+    }
+}
+
+impl Drop for Search {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::RingBuffer;
+    use crate::{LogRecord, LogStream};
+    use chrono::Utc;
+
+    fn record(raw: &str) -> LogRecord {
+        LogRecord {
+            seq: 0,
+            timestamp: Utc::now(),
+            stream: LogStream::Stdout,
+            container: Arc::from("app_web_1"),
+            replica: 1,
+            raw: raw.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_query() {
+        let query = Query::new("a.c", QueryOptions::default()).unwrap();
+        assert!(query.is_match("xa.cx") && !query.is_match("abc"));
+
+        let options = QueryOptions {
+            regex: true,
+            ignore_case: true,
+        };
+        let query = Query::new("a.c", options).unwrap();
+        assert_eq!(query.find_iter("ABC adc").collect::<Vec<_>>(), [0..3, 4..7]);
+
+        let e = Query::new("(", options).unwrap_err();
+        assert!(matches!(e, Error::InvalidPattern(_)));
+    }
+
+    #[tokio::test]
+    async fn test_search() {
+        let mut store = RingBuffer::default();
+        for i in 0..2500 {
+            store.push(record(&format!("line {}", i))).unwrap();
+        }
+        let logs: Arc<Mutex<Box<dyn LogStore>>> = Arc::new(Mutex::new(Box::new(store)));
+        let query = Query::new("line 1", QueryOptions::default()).unwrap();
+        let search = Search::start(logs.clone(), query);
+        while !search.is_done() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        // "line 1", "line 10".."line 19", "line 100".."line 199", "line 1000".."line 1999"
+        assert_eq!(search.len(), 1111);
+        assert!(search.contains(1) && !search.contains(2));
+
+        // Records stored afterwards are searched as well
+        logs.lock().unwrap().push(record("line 1 again")).unwrap();
+        while !search.contains(2500) {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+}
@@ -0,0 +1,145 @@
+use crate::{LogRecord, LogStream};
+use chrono::{TimeZone, Utc};
+use std::{
+    convert::TryInto,
+    io::{self, Read},
+};
+
+// Binary layout of a record, all integers little endian:
+//
+//   u32  length of the rest
+//   u64  seq
+//   i64  seconds since the Unix epoch
+//   u32  nanoseconds
+//   u8   stream (1: stdout, 2: stderr, 3: event)
+//   u32  replica
+//   u16  container name length, followed by the UTF-8 bytes
+//   u32  raw line length, followed by the UTF-8 bytes
+
+pub fn encode(record: &LogRecord, out: &mut Vec<u8>) {
+    let start = out.len();
+    out.extend_from_slice(&[0; 4]);
+    out.extend_from_slice(&record.seq.to_le_bytes());
+    out.extend_from_slice(&record.timestamp.timestamp().to_le_bytes());
+    out.extend_from_slice(&record.timestamp.timestamp_subsec_nanos().to_le_bytes());
+    out.push(match record.stream {
+        LogStream::Stdout => 1,
+        LogStream::Stderr => 2,
+        LogStream::Event => 3,
+    });
+    out.extend_from_slice(&record.replica.to_le_bytes());
+    let container = &record.container.as_bytes()[..record.container.len().min(u16::MAX as usize)];
+    out.extend_from_slice(&(container.len() as u16).to_le_bytes());
+    out.extend_from_slice(container);
+    out.extend_from_slice(&(record.raw.len() as u32).to_le_bytes());
+    out.extend_from_slice(record.raw.as_bytes());
+
+    let len = (out.len() - start - 4) as u32;
+    out[start..start + 4].copy_from_slice(&len.to_le_bytes());
+}
+
+/// Reads one record, or `None` at a clean end of input.
+pub fn decode(input: &mut impl Read) -> io::Result<Option<LogRecord>> {
+    let mut len = [0; 4];
+    match input.read_exact(&mut len) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    }
+    let mut body = vec![0; u32::from_le_bytes(len) as usize];
+    input.read_exact(&mut body)?;
+    decode_body(&body).map(Some)
+}
+
+fn decode_body(body: &[u8]) -> io::Result<LogRecord> {
+    let mut cursor = Cursor { body, at: 0 };
+    let seq = u64::from_le_bytes(cursor.take(8)?.try_into().unwrap());
+    let secs = i64::from_le_bytes(cursor.take(8)?.try_into().unwrap());
+    let nanos = u32::from_le_bytes(cursor.take(4)?.try_into().unwrap());
+    let stream = match cursor.take(1)?[0] {
+        2 => LogStream::Stderr,
+        3 => LogStream::Event,
+        _ => LogStream::Stdout,
+    };
+    let replica = u32::from_le_bytes(cursor.take(4)?.try_into().unwrap());
+    let len = u16::from_le_bytes(cursor.take(2)?.try_into().unwrap()) as usize;
+    let container = String::from_utf8_lossy(cursor.take(len)?);
+    let len = u32::from_le_bytes(cursor.take(4)?.try_into().unwrap()) as usize;
+    let raw = String::from_utf8_lossy(cursor.take(len)?).into_owned();
+
+    let timestamp = Utc
+        .timestamp_opt(secs, nanos)
+        .single()
+        .ok_or_else(|| invalid("timestamp out of range"))?;
+    Ok(LogRecord {
+        seq,
+        timestamp,
+        stream,
+        container: container.as_ref().into(),
+        replica,
+        raw,
+    })
+}
+
+struct Cursor<'a> {
+    body: &'a [u8],
+    at: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn take(&mut self, n: usize) -> io::Result<&'a [u8]> {
+        let bytes = self
+            .body
+            .get(self.at..self.at + n)
+            .ok_or_else(|| invalid("truncated record"))?;
+        self.at += n;
+        Ok(bytes)
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_roundtrip() {
+        let record = LogRecord {
+            seq: 42,
+            timestamp: Utc.timestamp_opt(1619863200, 123456789).unwrap(),
+            stream: LogStream::Stderr,
+            container: "app_web_2".into(),
+            replica: 2,
+            raw: "panicked at 'oops' ✗".into(),
+        };
+        let mut buf = vec![];
+        encode(&record, &mut buf);
+        encode(&record, &mut buf);
+
+        let mut input = buf.as_slice();
+        assert_eq!(decode(&mut input).unwrap(), Some(record.clone()));
+        assert_eq!(decode(&mut input).unwrap(), Some(record));
+        assert_eq!(decode(&mut input).unwrap(), None);
+    }
+
+    #[test]
+    fn test_truncated() {
+        let mut buf = vec![];
+        encode(
+            &LogRecord {
+                seq: 0,
+                timestamp: Utc::now(),
+                stream: LogStream::Stdout,
+                container: "c".into(),
+                replica: 1,
+                raw: "line".into(),
+            },
+            &mut buf,
+        );
+        buf.truncate(buf.len() - 2);
+        assert!(decode(&mut buf.as_slice()).is_err());
+    }
+}
@@ -0,0 +1,52 @@
+mod codec;
+mod ring;
+mod segmented;
+
+pub use ring::{parse_size, Retention, RingBuffer};
+pub use segmented::{SegmentedStore, DEFAULT_SEGMENT_BYTES};
+
+use crate::LogRecord;
+use std::{io, ops::Range, path::PathBuf};
+
+/// Where a `LogCollector` keeps its records.
+///
+/// Records get consecutive sequence numbers starting from 0. A store may evict old records,
+/// but never renumbers the remaining ones, so a sequence number below `first_seq` tells that
+/// the record it refers to is gone.
+pub trait LogStore: Send {
+    /// Appends a record and returns the sequence number assigned to it.
+    fn push(&mut self, record: LogRecord) -> io::Result<u64>;
+
+    /// Sequence number of the oldest record still held.
+    fn first_seq(&self) -> u64;
+
+    /// Sequence number the next pushed record will get.
+    fn next_seq(&self) -> u64;
+
+    fn get(&self, seq: u64) -> Option<LogRecord>;
+
+    /// Returns held records within the range, skipping evicted ones.
+    fn range(&self, range: Range<u64>) -> Vec<LogRecord>;
+
+    fn len(&self) -> usize {
+        (self.next_seq() - self.first_seq()) as usize
+    }
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Directory for a new disk-backed session of the project, under the user's cache directory.
+pub fn session_dir(project: &str) -> PathBuf {
+    let cache = std::env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+        .unwrap_or_else(std::env::temp_dir);
+    cache.join("decom").join("sessions").join(format!(
+        "{}-{}-{}",
+        project,
+        chrono::Local::now().format("%Y%m%d%H%M%S"),
+        std::process::id()
+    ))
+}
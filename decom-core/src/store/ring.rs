@@ -0,0 +1,229 @@
+use super::LogStore;
+use crate::error::Error;
+use crate::LogRecord;
+use std::{collections::VecDeque, io, mem, ops::Range, str::FromStr};
+
+/// Limits of a `RingBuffer`. Oldest records are evicted once either limit is exceeded.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Retention {
+    pub max_lines: Option<usize>,
+    pub max_bytes: Option<usize>,
+}
+
+impl Default for Retention {
+    fn default() -> Self {
+        Retention {
+            max_lines: Some(100_000),
+            max_bytes: Some(64 * 1024 * 1024),
+        }
+    }
+}
+
+impl Retention {
+    pub fn unbounded() -> Self {
+        Retention {
+            max_lines: None,
+            max_bytes: None,
+        }
+    }
+}
+
+impl FromStr for Retention {
+    type Err = Error;
+
+    /// Parses limits separated by commas, a number of lines such as `50000` or a size such as
+    /// `32MiB`, or `unbounded`. Limits which aren't given stay at the default.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut retention = Retention::default();
+        for limit in s.split(',').map(str::trim) {
+            if limit == "unbounded" || limit == "none" {
+                retention = Retention::unbounded();
+            } else if let Ok(lines) = limit.parse() {
+                retention.max_lines = Some(lines);
+            } else if let Some(bytes) = parse_size(limit) {
+                retention.max_bytes = Some(bytes);
+            } else {
+                return Err(Error::InvalidConfig(format!(
+                    "invalid retention `{}`, expected a number of lines, a size such as 32MiB or unbounded",
+                    s
+                )));
+            }
+        }
+        Ok(retention)
+    }
+}
+
+/// Parses a size such as `4096`, `64K`, `32MiB` or `1GB` into bytes. Units are powers of 1024.
+pub fn parse_size(s: &str) -> Option<usize> {
+    let digits = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let n: usize = s[..digits].parse().ok()?;
+    let unit = match s[digits..].trim_start().to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "k" | "kb" | "kib" => 1 << 10,
+        "m" | "mb" | "mib" => 1 << 20,
+        "g" | "gb" | "gib" => 1 << 30,
+        _ => return None,
+    };
+    n.checked_mul(unit)
+}
+
+/// In-memory store which keeps only the latest records.
+pub struct RingBuffer {
+    retention: Retention,
+    records: VecDeque<LogRecord>,
+    first_seq: u64,
+    bytes: usize,
+}
+
+impl RingBuffer {
+    pub fn new(retention: Retention) -> Self {
+        RingBuffer {
+            retention,
+            records: VecDeque::new(),
+            first_seq: 0,
+            bytes: 0,
+        }
+    }
+
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+
+    fn over(&self) -> bool {
+        let lines = self
+            .retention
+            .max_lines
+            .is_some_and(|max| self.records.len() > max);
+        let bytes = self.retention.max_bytes.is_some_and(|max| self.bytes > max);
+        lines || bytes
+    }
+}
+
+impl Default for RingBuffer {
+    fn default() -> Self {
+        RingBuffer::new(Retention::default())
+    }
+}
+
+fn size_of(record: &LogRecord) -> usize {
+    mem::size_of::<LogRecord>() + record.raw.len()
+}
+
+impl LogStore for RingBuffer {
+    fn push(&mut self, mut record: LogRecord) -> io::Result<u64> {
+        let seq = self.next_seq();
+        record.seq = seq;
+        self.bytes += size_of(&record);
+        self.records.push_back(record);
+
+        // Always keep the latest record, even if it alone exceeds the limits
+        while self.records.len() > 1 && self.over() {
+            if let Some(evicted) = self.records.pop_front() {
+                self.bytes -= size_of(&evicted);
+                self.first_seq += 1;
+            }
+        }
+        Ok(seq)
+    }
+
+    fn first_seq(&self) -> u64 {
+        self.first_seq
+    }
+
+    fn next_seq(&self) -> u64 {
+        self.first_seq + self.records.len() as u64
+    }
+
+    fn get(&self, seq: u64) -> Option<LogRecord> {
+        let index = seq.checked_sub(self.first_seq)?;
+        self.records.get(index as usize).cloned()
+    }
+
+    fn range(&self, range: Range<u64>) -> Vec<LogRecord> {
+        let start = range.start.max(self.first_seq);
+        let end = range.end.min(self.next_seq());
+        if start >= end {
+            return vec![];
+        }
+        self.records
+            .range((start - self.first_seq) as usize..(end - self.first_seq) as usize)
+            .cloned()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::LogStream;
+    use chrono::Utc;
+
+    fn record(raw: &str) -> LogRecord {
+        LogRecord {
+            seq: 0,
+            timestamp: Utc::now(),
+            stream: LogStream::Stdout,
+            container: "app_web_1".into(),
+            replica: 1,
+            raw: raw.into(),
+        }
+    }
+
+    fn raws(records: Vec<LogRecord>) -> Vec<String> {
+        records.into_iter().map(|r| r.raw).collect()
+    }
+
+    #[test]
+    fn test_evict_by_lines() {
+        let mut store = RingBuffer::new(Retention {
+            max_lines: Some(3),
+            max_bytes: None,
+        });
+        for i in 0..5 {
+            assert_eq!(store.push(record(&i.to_string())).unwrap(), i);
+        }
+        assert_eq!(store.first_seq(), 2);
+        assert_eq!(store.next_seq(), 5);
+        assert_eq!(store.len(), 3);
+        assert_eq!(store.get(1), None);
+        assert_eq!(store.get(2).map(|r| r.seq), Some(2));
+        assert_eq!(raws(store.range(0..4)), vec!["2", "3"]);
+        assert_eq!(raws(store.range(4..10)), vec!["4"]);
+        assert!(store.range(7..9).is_empty());
+    }
+
+    #[test]
+    fn test_evict_by_bytes() {
+        let unit = size_of(&record("0123456789"));
+        let mut store = RingBuffer::new(Retention {
+            max_lines: None,
+            max_bytes: Some(unit * 2),
+        });
+        store.push(record("0123456789")).unwrap();
+        store.push(record("0123456789")).unwrap();
+        assert_eq!(store.len(), 2);
+        assert_eq!(store.bytes(), unit * 2);
+
+        store.push(record("01234567890123456789")).unwrap();
+        assert_eq!(store.first_seq(), 2);
+        assert_eq!(store.len(), 1);
+    }
+
+    #[test]
+    fn test_parse_retention() {
+        let retention: Retention = "5000, 32MiB".parse().unwrap();
+        assert_eq!(retention.max_lines, Some(5000));
+        assert_eq!(retention.max_bytes, Some(32 << 20));
+        let retention: Retention = "512k".parse().unwrap();
+        assert_eq!(retention.max_lines, Retention::default().max_lines);
+        assert_eq!(retention.max_bytes, Some(512 << 10));
+        assert_eq!(
+            "unbounded".parse::<Retention>().unwrap(),
+            Retention::unbounded()
+        );
+        assert!("lots".parse::<Retention>().is_err());
+        assert_eq!(parse_size("1GB"), Some(1 << 30));
+        assert_eq!(parse_size("10 MB"), Some(10 << 20));
+        assert_eq!(parse_size("MB"), None);
+    }
+}
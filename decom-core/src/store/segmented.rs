@@ -0,0 +1,384 @@
+use super::{codec, LogStore, Retention};
+use crate::LogRecord;
+use chrono::{DateTime, Utc};
+use std::{
+    collections::VecDeque,
+    convert::TryInto,
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    ops::Range,
+    os::unix::fs::FileExt,
+    path::{Path, PathBuf},
+};
+
+pub const DEFAULT_SEGMENT_BYTES: u64 = 64 * 1024 * 1024;
+
+// Each index entry is the offset of the record in the log file and its timestamp in nanoseconds.
+const INDEX_ENTRY: u64 = 16;
+
+struct Segment {
+    first_seq: u64,
+    count: u64,
+    bytes: u64,
+    log: File,
+    index: File,
+    paths: [PathBuf; 2],
+}
+
+impl Segment {
+    fn create(dir: &Path, first_seq: u64) -> io::Result<Segment> {
+        let log_path = dir.join(format!("{:020}.log", first_seq));
+        let index_path = dir.join(format!("{:020}.idx", first_seq));
+        let open = |path: &Path| {
+            OpenOptions::new()
+                .read(true)
+                .append(true)
+                .create(true)
+                .open(path)
+        };
+        let log = open(&log_path)?;
+        let index = open(&index_path)?;
+        log.set_len(0)?;
+        index.set_len(0)?;
+        Ok(Segment {
+            first_seq,
+            count: 0,
+            bytes: 0,
+            log,
+            index,
+            paths: [log_path, index_path],
+        })
+    }
+
+    // Appends an encoded record and its index entry, or neither of them
+    fn write(&mut self, record: &[u8], entry: &[u8]) -> io::Result<()> {
+        let written = self
+            .log
+            .write_all(record)
+            .and_then(|()| self.index.write_all(entry));
+        if let Err(e) = written {
+            // Cut off what was written of them, so that later offsets stay right
+            let _ = self.log.set_len(self.bytes);
+            let _ = self.index.set_len(self.count * INDEX_ENTRY);
+            return Err(e);
+        }
+        self.bytes += record.len() as u64;
+        self.count += 1;
+        Ok(())
+    }
+
+    fn entry(&self, seq: u64) -> io::Result<(u64, i64)> {
+        let mut buf = [0; INDEX_ENTRY as usize];
+        self.index
+            .read_exact_at(&mut buf, (seq - self.first_seq) * INDEX_ENTRY)?;
+        Ok((
+            u64::from_le_bytes(buf[..8].try_into().unwrap()),
+            i64::from_le_bytes(buf[8..].try_into().unwrap()),
+        ))
+    }
+
+    fn offset(&self, seq: u64) -> io::Result<u64> {
+        if seq >= self.first_seq + self.count {
+            Ok(self.bytes)
+        } else {
+            self.entry(seq).map(|(offset, _)| offset)
+        }
+    }
+
+    /// Reads records of the range, which must lie within this segment.
+    fn read(&self, range: Range<u64>) -> io::Result<Vec<LogRecord>> {
+        let start = self.offset(range.start)?;
+        let end = self.offset(range.end)?;
+        let mut buf = vec![0; (end - start) as usize];
+        self.log.read_exact_at(&mut buf, start)?;
+
+        let mut input = buf.as_slice();
+        let mut records = Vec::with_capacity((range.end - range.start) as usize);
+        while let Some(record) = codec::decode(&mut input)? {
+            records.push(record);
+        }
+        Ok(records)
+    }
+
+    fn remove(self) -> io::Result<()> {
+        let Segment { paths, .. } = self;
+        fs::remove_file(&paths[0])?;
+        fs::remove_file(&paths[1])
+    }
+}
+
+/// Store which appends records to segment files on disk, so that it holds far more than fits in memory.
+///
+/// Every segment is a pair of files named after the sequence number of its first record:
+/// `.log` with the encoded records and `.idx` with a fixed-size entry per record.
+/// Retention is applied by deleting whole segments.
+pub struct SegmentedStore {
+    dir: PathBuf,
+    retention: Retention,
+    segment_bytes: u64,
+    segments: VecDeque<Segment>,
+    next_seq: u64,
+    buf: Vec<u8>,
+}
+
+impl SegmentedStore {
+    /// Creates an empty store in the directory, removing segments left there.
+    pub fn create(
+        dir: impl Into<PathBuf>,
+        retention: Retention,
+        segment_bytes: u64,
+    ) -> io::Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        // An earlier session which didn't end cleanly may have left some
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if let Some("log") | Some("idx") = path.extension().and_then(|e| e.to_str()) {
+                fs::remove_file(&path)?;
+            }
+        }
+        Ok(SegmentedStore {
+            dir,
+            retention,
+            segment_bytes,
+            segments: VecDeque::new(),
+            next_seq: 0,
+            buf: vec![],
+        })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn bytes(&self) -> u64 {
+        self.segments
+            .iter()
+            .map(|s| s.bytes + s.count * INDEX_ENTRY)
+            .sum()
+    }
+
+    /// Returns the sequence number of the first record at or after the timestamp.
+    /// Records are assumed to be stored in roughly chronological order.
+    pub fn seek(&self, timestamp: DateTime<Utc>) -> u64 {
+        let target = nanos(&timestamp);
+        let (mut lo, mut hi) = (self.first_seq(), self.next_seq);
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            let before = self
+                .segment(mid)
+                .and_then(|s| s.entry(mid).ok())
+                .is_some_and(|(_, t)| t < target);
+            if before {
+                lo = mid + 1;
+            } else {
+                hi = mid;
+            }
+        }
+        lo
+    }
+
+    fn segment(&self, seq: u64) -> Option<&Segment> {
+        if seq < self.first_seq() || seq >= self.next_seq {
+            return None;
+        }
+        let at = self.segments.partition_point(|s| s.first_seq <= seq);
+        self.segments.get(at - 1)
+    }
+
+    fn append(&mut self, record: &LogRecord) -> io::Result<()> {
+        let full = self
+            .segments
+            .back()
+            .is_none_or(|s| s.bytes >= self.segment_bytes);
+        if full {
+            self.segments
+                .push_back(Segment::create(&self.dir, self.next_seq)?);
+        }
+
+        self.buf.clear();
+        codec::encode(record, &mut self.buf);
+        let segment = self.segments.back_mut().unwrap();
+        let mut entry = [0; INDEX_ENTRY as usize];
+        entry[..8].copy_from_slice(&segment.bytes.to_le_bytes());
+        entry[8..].copy_from_slice(&nanos(&record.timestamp).to_le_bytes());
+
+        segment.write(&self.buf, &entry)
+    }
+
+    fn over(&self) -> bool {
+        let lines = self.retention.max_lines.is_some_and(|max| self.len() > max);
+        let bytes = self
+            .retention
+            .max_bytes
+            .is_some_and(|max| self.bytes() > max as u64);
+        lines || bytes
+    }
+}
+
+fn nanos(timestamp: &DateTime<Utc>) -> i64 {
+    timestamp
+        .timestamp()
+        .saturating_mul(1_000_000_000)
+        .saturating_add(timestamp.timestamp_subsec_nanos() as i64)
+}
+
+impl LogStore for SegmentedStore {
+    fn push(&mut self, mut record: LogRecord) -> io::Result<u64> {
+        let seq = self.next_seq;
+        record.seq = seq;
+        self.append(&record)?;
+        self.next_seq += 1;
+
+        // The segment being written is never evicted
+        while self.segments.len() > 1 && self.over() {
+            if let Some(segment) = self.segments.pop_front() {
+                segment.remove()?;
+            }
+        }
+        Ok(seq)
+    }
+
+    fn first_seq(&self) -> u64 {
+        self.segments.front().map_or(self.next_seq, |s| s.first_seq)
+    }
+
+    fn next_seq(&self) -> u64 {
+        self.next_seq
+    }
+
+    fn get(&self, seq: u64) -> Option<LogRecord> {
+        let segment = self.segment(seq)?;
+        segment.read(seq..seq + 1).ok()?.pop()
+    }
+
+    fn range(&self, range: Range<u64>) -> Vec<LogRecord> {
+        let mut records = vec![];
+        let mut seq = range.start.max(self.first_seq());
+        let end = range.end.min(self.next_seq);
+        while seq < end {
+            let segment = match self.segment(seq) {
+                Some(segment) => segment,
+                None => break,
+            };
+            let until = end.min(segment.first_seq + segment.count);
+            match segment.read(seq..until) {
+                Ok(read) => records.extend(read),
+                Err(_) => break,
+            }
+            seq = until;
+        }
+        records
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::LogStream;
+    use chrono::TimeZone;
+
+    fn dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("decom-segmented-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn record(secs: i64) -> LogRecord {
+        LogRecord {
+            seq: 0,
+            timestamp: Utc.timestamp_opt(secs, 0).unwrap(),
+            stream: LogStream::Stdout,
+            container: "app_web_1".into(),
+            replica: 1,
+            raw: format!("line {}", secs),
+        }
+    }
+
+    fn segments(dir: &Path) -> usize {
+        fs::read_dir(dir)
+            .unwrap()
+            .filter(|e| e.as_ref().unwrap().path().extension() == Some("log".as_ref()))
+            .count()
+    }
+
+    #[test]
+    fn test_push_and_read() {
+        let dir = dir("read");
+        let size = {
+            let mut buf = vec![];
+            codec::encode(&record(0), &mut buf);
+            buf.len() as u64
+        };
+        // Three records per segment
+        let mut store = SegmentedStore::create(&dir, Retention::unbounded(), size * 3).unwrap();
+        for i in 0..10 {
+            assert_eq!(store.push(record(i)).unwrap(), i as u64);
+        }
+        assert_eq!(segments(&dir), 4);
+        assert_eq!(store.len(), 10);
+        assert_eq!(store.get(4).unwrap().raw, "line 4");
+        assert_eq!(store.get(4).unwrap().seq, 4);
+        assert_eq!(store.get(10), None);
+
+        let raws: Vec<String> = store.range(2..8).into_iter().map(|r| r.raw).collect();
+        assert_eq!(
+            raws,
+            vec!["line 2", "line 3", "line 4", "line 5", "line 6", "line 7"]
+        );
+
+        assert_eq!(store.seek(Utc.timestamp_opt(5, 0).unwrap()), 5);
+        assert_eq!(store.seek(Utc.timestamp_opt(-1, 0).unwrap()), 0);
+        assert_eq!(store.seek(Utc.timestamp_opt(99, 0).unwrap()), 10);
+
+        // Segments of the store are gone once another one is created in the directory
+        drop(store);
+        let mut store = SegmentedStore::create(&dir, Retention::unbounded(), size * 3).unwrap();
+        assert_eq!(segments(&dir), 0);
+        store.push(record(20)).unwrap();
+        assert_eq!(store.get(0).unwrap().raw, "line 20");
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_failed_write() {
+        let dir = dir("failed");
+        let mut store = SegmentedStore::create(&dir, Retention::unbounded(), 1 << 20).unwrap();
+        store.push(record(0)).unwrap();
+        // The index can't be written to, e.g. once the disk is full
+        let segment = store.segments.back_mut().unwrap();
+        let index = std::mem::replace(&mut segment.index, File::open(&segment.paths[1]).unwrap());
+        let bytes = segment.bytes;
+        assert!(store.push(record(1)).is_err());
+
+        let segment = store.segments.back_mut().unwrap();
+        assert_eq!(segment.log.metadata().unwrap().len(), bytes);
+        segment.index = index;
+        store.push(record(2)).unwrap();
+        assert_eq!(store.get(1).unwrap().raw, "line 2");
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_retention() {
+        let dir = dir("retention");
+        let mut store = SegmentedStore::create(
+            &dir,
+            Retention {
+                max_lines: Some(4),
+                max_bytes: None,
+            },
+            1,
+        )
+        .unwrap();
+        for i in 0..6 {
+            store.push(record(i)).unwrap();
+        }
+        assert_eq!(store.first_seq(), 2);
+        assert_eq!(store.get(1), None);
+        assert_eq!(store.range(0..6).len(), 4);
+        assert_eq!(segments(&dir), 4);
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,209 @@
+use crate::LogCollector;
+use chrono::{DateTime, Utc};
+use std::{cmp::Reverse, collections::BinaryHeap};
+
+/// Position of a record in the merged timeline.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Entry {
+    pub timestamp: DateTime<Utc>,
+    /// Index of the collector which holds the record.
+    pub source: usize,
+    pub seq: u64,
+}
+
+/// Interleaves records of several collectors in timestamp order.
+pub struct Timeline {
+    entries: Vec<Entry>,
+    cursors: Vec<u64>,
+    floors: Vec<u64>,
+}
+
+impl Timeline {
+    pub fn new(sources: usize) -> Self {
+        Timeline {
+            entries: vec![],
+            cursors: vec![0; sources],
+            floors: vec![0; sources],
+        }
+    }
+
+    /// Pulls records stored since the last update. Returns the number of new entries.
+    pub fn update(&mut self, collectors: &[LogCollector]) -> usize {
+        if self.cursors.len() < collectors.len() {
+            self.cursors.resize(collectors.len(), 0);
+            self.floors.resize(collectors.len(), 0);
+        }
+
+        let floors: Vec<u64> = collectors.iter().map(LogCollector::first_seq).collect();
+        if floors.iter().zip(&self.floors).any(|(new, old)| new > old) {
+            self.evict(&floors);
+        }
+
+        let batches: Vec<Vec<Entry>> = collectors
+            .iter()
+            .enumerate()
+            .map(|(source, collector)| {
+                let records = collector.since(self.cursors[source]);
+                if let Some(last) = records.last() {
+                    self.cursors[source] = last.seq + 1;
+                }
+                records
+                    .iter()
+                    .map(|record| Entry {
+                        timestamp: record.timestamp,
+                        source,
+                        seq: record.seq,
+                    })
+                    .collect()
+            })
+            .collect();
+        self.merge(batches)
+    }
+
+    /// Merges new entries, which may be older than ones already in the timeline.
+    pub fn merge(&mut self, batches: Vec<Vec<Entry>>) -> usize {
+        let mut runs: Vec<Vec<Entry>> = batches
+            .into_iter()
+            .filter(|batch| !batch.is_empty())
+            .map(|mut batch| {
+                // A collector follows several replicas, so its records are only roughly ordered.
+                batch.sort();
+                batch
+            })
+            .collect();
+        let count: usize = runs.iter().map(Vec::len).sum();
+        let oldest = match runs.iter().map(|run| run[0]).min() {
+            Some(entry) => entry,
+            None => return 0,
+        };
+
+        // Only the part of the timeline newer than the oldest new entry has to be merged again.
+        let at = self.entries.partition_point(|entry| *entry < oldest);
+        let tail = self.entries.split_off(at);
+        if !tail.is_empty() {
+            runs.push(tail);
+        }
+        self.entries.extend(kway_merge(runs));
+        count
+    }
+
+    /// Drops entries whose records have been evicted from their collector.
+    pub fn evict(&mut self, floors: &[u64]) {
+        self.entries.retain(|entry| {
+            floors
+                .get(entry.source)
+                .is_none_or(|floor| entry.seq >= *floor)
+        });
+        self.floors = floors.to_vec();
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+}
+
+fn kway_merge(runs: Vec<Vec<Entry>>) -> Vec<Entry> {
+    let mut merged = Vec::with_capacity(runs.iter().map(Vec::len).sum());
+    let mut heap: BinaryHeap<Reverse<(Entry, usize, usize)>> = runs
+        .iter()
+        .enumerate()
+        .map(|(run, entries)| Reverse((entries[0], run, 0)))
+        .collect();
+
+    while let Some(Reverse((entry, run, index))) = heap.pop() {
+        merged.push(entry);
+        if let Some(next) = runs[run].get(index + 1) {
+            heap.push(Reverse((*next, run, index + 1)));
+        }
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn entry(secs: i64, source: usize, seq: u64) -> Entry {
+        Entry {
+            timestamp: Utc.timestamp_opt(secs, 0).unwrap(),
+            source,
+            seq,
+        }
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut timeline = Timeline::new(3);
+        let count = timeline.merge(vec![
+            vec![entry(1, 0, 0), entry(4, 0, 1)],
+            vec![entry(2, 1, 0), entry(3, 1, 1)],
+            vec![],
+        ]);
+        assert_eq!(count, 4);
+        assert_eq!(
+            timeline.entries(),
+            &[
+                entry(1, 0, 0),
+                entry(2, 1, 0),
+                entry(3, 1, 1),
+                entry(4, 0, 1)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_merge_late_arrival() {
+        let mut timeline = Timeline::new(3);
+        timeline.merge(vec![
+            vec![entry(1, 0, 0), entry(5, 0, 1)],
+            vec![entry(6, 1, 0)],
+        ]);
+
+        // Source 2 delivers lines which happened before ones already merged
+        timeline.merge(vec![vec![], vec![], vec![entry(3, 2, 1), entry(2, 2, 0)]]);
+        assert_eq!(
+            timeline.entries(),
+            &[
+                entry(1, 0, 0),
+                entry(2, 2, 0),
+                entry(3, 2, 1),
+                entry(5, 0, 1),
+                entry(6, 1, 0)
+            ]
+        );
+
+        timeline.merge(vec![vec![entry(7, 0, 2)]]);
+        assert_eq!(timeline.entries().last(), Some(&entry(7, 0, 2)));
+        assert_eq!(timeline.len(), 6);
+    }
+
+    #[test]
+    fn test_evict() {
+        let mut timeline = Timeline::new(2);
+        timeline.merge(vec![
+            vec![entry(1, 0, 0), entry(3, 0, 1)],
+            vec![entry(2, 1, 0), entry(4, 1, 1)],
+        ]);
+        timeline.evict(&[1, 0]);
+        assert_eq!(
+            timeline.entries(),
+            &[entry(2, 1, 0), entry(3, 0, 1), entry(4, 1, 1)]
+        );
+    }
+
+    #[test]
+    fn test_merge_ties() {
+        let mut timeline = Timeline::new(2);
+        timeline.merge(vec![vec![entry(1, 1, 0)], vec![entry(1, 0, 0)]]);
+        assert_eq!(timeline.entries(), &[entry(1, 0, 0), entry(1, 1, 0)]);
+    }
+}